@@ -1,35 +1,65 @@
 use crate::{
     Controllers,
     integrations::{
-        DiscordController, DiscordNotifier, NotifierRegistry, TelegramIntegration,
-        TerminalIntegration,
+        DiscordController, DiscordNotifier, EmailNotifier, MatrixIntegration, NotifierRegistry,
+        PushNotifier, PushServer, PushSettings, SmtpSettings, SmtpTls, TelegramIntegration,
+        TerminalFormat, TerminalIntegration, WebhookNotifier,
     },
+    models::{ChannelId, SITE_TIMEZONE},
+    monitor::MonitorSettings,
+    web::HttpSettings,
+};
+use chrono_tz::Tz;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
 };
-use std::{collections::HashSet, sync::Arc};
 use teloxide::Bot;
 
 pub struct AppConfig {
     pub integrations: HashSet<Integration>,
     pub disable_saving: bool,
+    /// Receives operational alerts, e.g. when a marketplace changes its layout.
+    pub admin_channel: Option<ChannelId>,
+    /// Timestamps are shown to users in this timezone.
+    pub display_timezone: Tz,
+    /// The HTTP server (feeds, dashboard) is only started if this is set.
+    pub http: Option<HttpSettings>,
+    /// Where the subscriptions and their state are saved.
+    pub data_dir: PathBuf,
+    /// The delay between starting the saved subscriptions, so they aren't all fetched at once.
+    pub stagger: Duration,
+    pub monitor: MonitorSettings,
+    /// The interval of subscriptions that don't set one, in seconds.
+    pub default_interval: u64,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Integration {
     Telegram { token: String },
     Discord { token: String },
-    Terminal,
+    Terminal { format: TerminalFormat },
+    Webhook { url: String, secret: Option<String> },
+    Email(SmtpSettings),
+    Matrix { homeserver: String, token: String },
+    Push(PushSettings),
 }
 
 impl AppConfig {
     pub fn get_config() -> Result<AppConfig, String> {
         // this is for testing only.
         let _ = dotenv::from_filename(".env");
+        let vars = ConfigVars::load()?;
 
         let mut integrations = HashSet::new();
+        // The API is served by the HTTP server, so it's not in `integrations`.
+        let mut api_tokens = None;
 
-        for integration in get_env_var("INTEGRATIONS")
-            .expect("INTEGRATIONS not found in env")
-            .clone()
+        for integration in vars
+            .get("INTEGRATIONS")
+            .map_err(|_| "INTEGRATIONS not set")?
             .split(',')
             .filter(|s| !s.is_empty())
         // filter dangling commas at the end of config
@@ -37,50 +67,213 @@ impl AppConfig {
         {
             match integration.to_uppercase().as_str() {
                 "TELEGRAM" => {
-                    let token = get_env_var("TELEGRAM_TOKEN")
-                        .map_err(|_| "TELEGRAM_TOKEN not found in env")?;
+                    let token = vars
+                        .get("TELEGRAM_TOKEN")
+                        .map_err(|_| "TELEGRAM_TOKEN not set")?;
                     integrations.insert(Integration::Telegram { token });
                 }
 
                 "DISCORD" => {
-                    let token = get_env_var("DISCORD_TOKEN")
-                        .map_err(|_| "DISCORD_TOKEN not found in env")?;
+                    let token = vars
+                        .get("DISCORD_TOKEN")
+                        .map_err(|_| "DISCORD_TOKEN not set")?;
                     integrations.insert(Integration::Discord { token });
                 }
                 // This just prints what would be sent as messages to telegram as text to the
                 // terminal. Mostly meant for debug purposes
                 "TERMINAL" => {
-                    integrations.insert(Integration::Terminal);
+                    let format = match vars
+                        .get("TERMINAL_FORMAT")
+                        .ok()
+                        .filter(|s| !s.is_empty())
+                        .as_deref()
+                        .map(str::to_lowercase)
+                        .as_deref()
+                    {
+                        None | Some("human") => TerminalFormat::Human,
+                        Some("rich") => TerminalFormat::Rich,
+                        Some("json") => TerminalFormat::Json,
+                        Some(other) => {
+                            return Err(format!(
+                                "Invalid TERMINAL_FORMAT: '{other}'. Use human, rich or json"
+                            ));
+                        }
+                    };
+                    integrations.insert(Integration::Terminal { format });
+                }
+                // Posts listings as JSON to a URL. Requests are signed if a secret is set.
+                "WEBHOOK" => {
+                    let url = vars.get("WEBHOOK_URL").map_err(|_| "WEBHOOK_URL not set")?;
+                    let secret = vars.get("WEBHOOK_SECRET").ok().filter(|s| !s.is_empty());
+                    integrations.insert(Integration::Webhook { url, secret });
+                }
+                "MATRIX" => {
+                    let homeserver = vars
+                        .get("MATRIX_HOMESERVER")
+                        .map_err(|_| "MATRIX_HOMESERVER not set")?;
+                    let token = vars
+                        .get("MATRIX_TOKEN")
+                        .map_err(|_| "MATRIX_TOKEN not set")?;
+                    integrations.insert(Integration::Matrix { homeserver, token });
+                }
+                // Phone notifications through an ntfy or a Gotify server.
+                "NTFY" => {
+                    integrations.insert(Integration::Push(PushSettings {
+                        server: PushServer::Ntfy,
+                        url: vars
+                            .get("NTFY_URL")
+                            .ok()
+                            .filter(|s| !s.is_empty())
+                            .unwrap_or_else(|| "https://ntfy.sh".to_string()),
+                        token: vars.get("NTFY_TOKEN").ok().filter(|s| !s.is_empty()),
+                    }));
+                }
+                "GOTIFY" => {
+                    let url = vars.get("GOTIFY_URL").map_err(|_| "GOTIFY_URL not set")?;
+                    let token = vars
+                        .get("GOTIFY_TOKEN")
+                        .map_err(|_| "GOTIFY_TOKEN not set")?;
+                    integrations.insert(Integration::Push(PushSettings {
+                        server: PushServer::Gotify,
+                        url,
+                        token: Some(token),
+                    }));
                 }
+                // A JSON API for managing subscriptions from other programs.
+                "API" => {
+                    if vars.get("API_ADDRESS").is_ok_and(|s| !s.is_empty()) {
+                        tracing::warn!(
+                            "API_ADDRESS is no longer used, the API is served on HTTP_ADDRESS"
+                        );
+                    }
+                    // e.g. API_TOKENS=token1,token2
+                    let tokens: Vec<String> = vars
+                        .get("API_TOKENS")
+                        .map_err(|_| "API_TOKENS not set")?
+                        .split(',')
+                        .map(|t| t.trim().to_string())
+                        .filter(|t| !t.is_empty())
+                        .collect();
+                    if tokens.is_empty() {
+                        return Err("API_TOKENS must contain at least one token".to_string());
+                    }
+                    api_tokens = Some(tokens);
+                }
+                "EMAIL" => {
+                    integrations.insert(Integration::Email(get_smtp_settings(&vars)?));
+                }
+                // It's better if we stop here rather than continue without it.
                 _ => {
-                    tracing::error!(
-                        "Invalid integration: '{}'. Check the INTEGRATIONS variable",
-                        integration
-                    );
-                    // It's better if we exit here rather than continue.
-                    std::process::exit(1);
+                    return Err(format!(
+                        "Invalid integration: '{integration}'. Check the INTEGRATIONS variable"
+                    ));
                 }
             }
         }
 
+        // Both would be sent to `ChannelId::Ntfy` channels.
+        if integrations
+            .iter()
+            .filter(|i| matches!(i, Integration::Push(_)))
+            .count()
+            > 1
+        {
+            return Err("ntfy and gotify can't be enabled at the same time".to_string());
+        }
+
         let mut disable_saving = false;
-        if get_env_var("DISABLE_SAVING").is_ok_and(|s| s == "true") {
+        if vars.get("DISABLE_SAVING").is_ok_and(|s| s == "true") {
             disable_saving = true;
         }
 
+        // e.g. ADMIN_CHANNEL=telegram:123456789
+        let admin_channel = match vars.get("ADMIN_CHANNEL") {
+            Ok(value) if !value.is_empty() => Some(parse_channel(&value)?),
+            _ => None,
+        };
+
+        // e.g. DISPLAY_TIMEZONE=Europe/London
+        let display_timezone = match vars.get("DISPLAY_TIMEZONE") {
+            Ok(value) if !value.is_empty() => value
+                .parse::<Tz>()
+                .map_err(|_| format!("Invalid DISPLAY_TIMEZONE: '{value}'"))?,
+            _ => SITE_TIMEZONE,
+        };
+
+        // e.g. HTTP_ADDRESS=0.0.0.0:8080
+        let http = match vars.get("HTTP_ADDRESS") {
+            Ok(value) if !value.is_empty() => {
+                let address = value
+                    .parse()
+                    .map_err(|_| format!("Invalid HTTP_ADDRESS: '{value}'"))?;
+                let public_url = vars
+                    .get("PUBLIC_URL")
+                    .ok()
+                    .filter(|s| !s.is_empty())
+                    .unwrap_or_else(|| format!("http://{address}"));
+                Some(HttpSettings {
+                    address,
+                    public_url: public_url.trim_end_matches('/').to_string(),
+                    dashboard_password: vars
+                        .get("DASHBOARD_PASSWORD")
+                        .ok()
+                        .filter(|s| !s.is_empty()),
+                    api_tokens,
+                })
+            }
+            _ if api_tokens.is_some() => {
+                return Err("The api integration needs HTTP_ADDRESS to be set".to_string());
+            }
+            _ => None,
+        };
+
+        let data_dir = vars
+            .get("DATA_DIR")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map_or_else(|| PathBuf::from("data"), PathBuf::from);
+        let stagger = Duration::from_millis(vars.number("STAGGER_MS", 1250)?);
+        let monitor = MonitorSettings {
+            notify_stagger: Duration::from_millis(vars.number("NOTIFY_STAGGER_MS", 100)?),
+            fetch_timeout: Duration::from_secs(vars.number("FETCH_TIMEOUT_SECS", 15)?),
+        };
+        let default_interval = vars.number("DEFAULT_INTERVAL_SECS", 60)?;
+        if default_interval == 0 {
+            return Err("DEFAULT_INTERVAL_SECS must be at least 1".to_string());
+        }
+
         Ok(AppConfig {
             integrations,
             disable_saving,
+            admin_channel,
+            display_timezone,
+            http,
+            data_dir,
+            stagger,
+            monitor,
+            default_interval,
         })
     }
 
-    pub async fn get_integrations(config: &AppConfig) -> (Controllers, NotifierRegistry) {
+    pub async fn get_integrations(
+        config: &AppConfig,
+    ) -> Result<(Controllers, NotifierRegistry), String> {
         // This is just so that we don't run without any integrations.
-        if config.integrations.is_empty() {
-            panic!("No integrations found. Please add them to the .env file.");
+        let api = config
+            .http
+            .as_ref()
+            .is_some_and(|http| http.api_tokens.is_some());
+        if config.integrations.is_empty() && !api {
+            return Err(
+                "No integrations found. Set INTEGRATIONS in the environment or the config file"
+                    .to_string(),
+            );
         }
 
-        let mut notifiers = NotifierRegistry::default();
+        let mut notifiers = NotifierRegistry {
+            admin_channel: config.admin_channel.clone(),
+            ..Default::default()
+        };
         let mut controllers: Controllers = Vec::new();
 
         // The set ensures that there is only one instance of each integration.
@@ -98,18 +291,356 @@ impl AppConfig {
                     notifiers.discord = Some(Arc::new(notifier));
                     controllers.push(Box::new(controller));
                 }
-                Integration::Terminal => {
-                    notifiers.terminal = Some(Arc::new(TerminalIntegration::new()))
+                Integration::Terminal { format } => {
+                    let integration = TerminalIntegration::new(*format);
+                    notifiers.terminal = Some(Arc::new(integration.clone()));
+                    controllers.push(Box::new(integration));
+                }
+                Integration::Webhook { url, secret } => {
+                    notifiers.webhook =
+                        Some(Arc::new(WebhookNotifier::new(url.clone(), secret.clone())))
+                }
+                Integration::Matrix { homeserver, token } => {
+                    let integration = MatrixIntegration::new(homeserver, token.clone())
+                        .map_err(|e| format!("Invalid Matrix settings: {e}"))?;
+                    notifiers.matrix = Some(Arc::new(integration.clone()));
+                    controllers.push(Box::new(integration));
+                }
+                Integration::Push(settings) => {
+                    notifiers.ntfy = Some(Arc::new(PushNotifier::new(settings.clone())))
+                }
+                Integration::Email(settings) => {
+                    notifiers.email = Some(
+                        EmailNotifier::new(settings)
+                            .map_err(|e| format!("Invalid email settings: {e}"))?,
+                    )
                 }
             }
         }
 
-        (controllers, notifiers)
+        Ok((controllers, notifiers))
+    }
+}
+
+/// Reads the SMTP_* variables. Only the host and the sender are required.
+fn get_smtp_settings(vars: &ConfigVars) -> Result<SmtpSettings, String> {
+    let host = vars.get("SMTP_HOST").map_err(|_| "SMTP_HOST not set")?;
+    let from = vars.get("SMTP_FROM").map_err(|_| "SMTP_FROM not set")?;
+    let optional = |key: &str| vars.get(key).ok().filter(|s| !s.is_empty());
+
+    let port = match optional("SMTP_PORT") {
+        Some(port) => Some(
+            port.parse::<u16>()
+                .map_err(|_| format!("Invalid SMTP_PORT: '{port}'"))?,
+        ),
+        None => None,
+    };
+    let tls = match optional("SMTP_TLS")
+        .as_deref()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        None | Some("starttls") => SmtpTls::StartTls,
+        Some("tls") => SmtpTls::Tls,
+        Some("none") => SmtpTls::None,
+        Some(other) => {
+            return Err(format!(
+                "Invalid SMTP_TLS: '{other}'. Use starttls, tls or none"
+            ));
+        }
+    };
+    let digest_interval = match optional("EMAIL_DIGEST_MINUTES") {
+        Some(minutes) => Some(std::time::Duration::from_secs(
+            60 * minutes
+                .parse::<u64>()
+                .ok()
+                .filter(|m| *m > 0)
+                .ok_or_else(|| format!("Invalid EMAIL_DIGEST_MINUTES: '{minutes}'"))?,
+        )),
+        None => None,
+    };
+
+    Ok(SmtpSettings {
+        host,
+        port,
+        username: optional("SMTP_USERNAME"),
+        password: optional("SMTP_PASSWORD"),
+        tls,
+        from,
+        digest_interval,
+    })
+}
+
+/// Parses a channel given as `platform:id`, e.g. `telegram:123456789` or `discord:987654321`.
+/// The terminal has no ID. The inverse of the `Display` of `ChannelId`.
+pub fn parse_channel(value: &str) -> Result<ChannelId, String> {
+    let (platform, id) = value.split_once(':').unwrap_or((value, ""));
+    let invalid = || format!("Invalid channel: '{value}'. Expected platform:id");
+
+    match platform.to_lowercase().as_str() {
+        "terminal" => Ok(ChannelId::Terminal),
+        "webhook" => Ok(ChannelId::Webhook),
+        "api" => Ok(ChannelId::Api),
+        // Room IDs contain a colon themselves, e.g. matrix:!abc:example.org
+        "matrix" if id.starts_with('!') => Ok(ChannelId::Matrix {
+            room_id: id.to_string(),
+        }),
+        "ntfy" if !id.is_empty() => Ok(ChannelId::Ntfy {
+            topic: id.to_string(),
+        }),
+        "email" if id.contains('@') => Ok(ChannelId::Email {
+            address: id.to_string(),
+        }),
+        "telegram" => Ok(ChannelId::Telegram {
+            chat_id: teloxide::types::ChatId(id.parse().map_err(|_| invalid())?),
+        }),
+        "discord" => Ok(ChannelId::Discord {
+            // Zero is not a valid ID, and would make `new` panic.
+            channel: serenity::model::id::ChannelId::new(
+                id.parse().ok().filter(|id| *id != 0).ok_or_else(invalid)?,
+            ),
+        }),
+        _ => Err(invalid()),
     }
 }
 
-/// We don't care about the types of errors 'round these parts.
-fn get_env_var(key: &str) -> Result<String, std::env::VarError> {
-    // dotenv::var(key).map_err(|_| ())
-    std::env::var(key)
+/// Where the configuration file is looked for if `CONFIG_FILE` is not set. It's fine if it
+/// doesn't exist.
+const DEFAULT_CONFIG_FILE: &str = "rozsdhabot.toml";
+
+/// The values of the configuration file, looked up by the names of the environment variables.
+/// Every key is the name of its variable in lowercase, with the first part turned into a table,
+/// e.g. `[telegram] token` is `TELEGRAM_TOKEN`. Arrays are joined with commas.
+///
+/// Environment variables override the file.
+#[derive(Debug, Default)]
+struct ConfigVars {
+    file: HashMap<String, String>,
+}
+
+impl ConfigVars {
+    fn load() -> Result<Self, String> {
+        let (path, required) = match std::env::var("CONFIG_FILE") {
+            Ok(path) if !path.is_empty() => (PathBuf::from(path), true),
+            _ => (PathBuf::from(DEFAULT_CONFIG_FILE), false),
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => {
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(format!("Failed to read {}: {e}", path.display())),
+        };
+        tracing::info!("Loading configuration from {}", path.display());
+        Self::parse(&text).map_err(|e| format!("Invalid {}: {e}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+        let mut file = HashMap::new();
+        flatten("", &table, &mut file)?;
+        Ok(Self { file })
+    }
+
+    /// Errors the same way `std::env::var` does, so the two are interchangeable.
+    fn get(&self, key: &str) -> Result<String, std::env::VarError> {
+        prefer_env(std::env::var(key), self.file.get(key))
+    }
+
+    /// A number that has a default. Set but invalid numbers are an error.
+    fn number<T: std::str::FromStr>(&self, key: &str, default: T) -> Result<T, String> {
+        match self.get(key) {
+            Ok(value) if !value.is_empty() => value
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid {key}: '{value}'")),
+            _ => Ok(default),
+        }
+    }
+}
+
+/// Empty variables don't override the file, e.g. the blank ones in docker-compose.yaml.
+fn prefer_env(
+    env: Result<String, std::env::VarError>,
+    file: Option<&String>,
+) -> Result<String, std::env::VarError> {
+    match (env, file) {
+        (Ok(value), Some(file)) if value.is_empty() => Ok(file.clone()),
+        (Err(_), Some(file)) => Ok(file.clone()),
+        (env, _) => env,
+    }
+}
+
+fn flatten(
+    prefix: &str,
+    table: &toml::Table,
+    out: &mut HashMap<String, String>,
+) -> Result<(), String> {
+    for (key, value) in table {
+        let name = match prefix {
+            "" => key.to_uppercase(),
+            _ => format!("{prefix}_{}", key.to_uppercase()),
+        };
+        let text = match value {
+            toml::Value::Table(table) => {
+                flatten(&name, table, out)?;
+                continue;
+            }
+            toml::Value::String(s) => s.clone(),
+            toml::Value::Array(values) => values
+                .iter()
+                .map(|v| match v {
+                    toml::Value::String(s) => Ok(s.clone()),
+                    toml::Value::Table(_) | toml::Value::Array(_) => {
+                        Err(format!("{name} can only contain plain values"))
+                    }
+                    other => Ok(other.to_string()),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .join(","),
+            other => other.to_string(),
+        };
+        out.insert(name, text);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_invalid_integration_settings() {
+        let config = |integration| AppConfig {
+            integrations: HashSet::from([integration]),
+            disable_saving: true,
+            admin_channel: None,
+            display_timezone: chrono_tz::Europe::Budapest,
+            http: None,
+            data_dir: PathBuf::from("data"),
+            stagger: Duration::ZERO,
+            monitor: MonitorSettings::default(),
+            default_interval: 60,
+        };
+
+        let matrix = config(Integration::Matrix {
+            homeserver: "matrix.example.org".to_string(),
+            token: String::new(),
+        });
+        let error = AppConfig::get_integrations(&matrix).await.err().unwrap();
+        assert!(error.starts_with("Invalid Matrix settings"));
+
+        let email = config(Integration::Email(SmtpSettings {
+            host: "127.0.0.1".to_string(),
+            port: None,
+            username: None,
+            password: None,
+            tls: SmtpTls::None,
+            from: "not an address".to_string(),
+            digest_interval: None,
+        }));
+        let error = AppConfig::get_integrations(&email).await.err().unwrap();
+        assert!(error.starts_with("Invalid email settings"));
+    }
+
+    #[test]
+    fn test_parse_channel() {
+        assert_eq!(
+            parse_channel("telegram:-100123"),
+            Ok(ChannelId::Telegram {
+                chat_id: teloxide::types::ChatId(-100123)
+            })
+        );
+        assert_eq!(
+            parse_channel("Discord:42"),
+            Ok(ChannelId::Discord {
+                channel: serenity::model::id::ChannelId::new(42)
+            })
+        );
+        assert_eq!(parse_channel("terminal"), Ok(ChannelId::Terminal));
+        for channel in [
+            ChannelId::Terminal,
+            ChannelId::Api,
+            ChannelId::Telegram {
+                chat_id: teloxide::types::ChatId(-100123),
+            },
+            ChannelId::Matrix {
+                room_id: "!abc:example.org".to_string(),
+            },
+        ] {
+            assert_eq!(parse_channel(&channel.to_string()), Ok(channel));
+        }
+        assert!(parse_channel("telegram:abc").is_err());
+        assert_eq!(
+            parse_channel("matrix:!abc:example.org"),
+            Ok(ChannelId::Matrix {
+                room_id: "!abc:example.org".to_string()
+            })
+        );
+        assert_eq!(
+            parse_channel("ntfy:deals"),
+            Ok(ChannelId::Ntfy {
+                topic: "deals".to_string()
+            })
+        );
+        assert!(parse_channel("discord:0").is_err());
+        assert!(parse_channel("irc:1").is_err());
+    }
+
+    #[test]
+    fn test_config_file() {
+        let vars = ConfigVars::parse(
+            r#"
+            integrations = ["terminal", "telegram"]
+            fetch_timeout_secs = 30
+            disable_saving = true
+            # Cargo sets this when running the tests, the environment wins.
+            cargo_pkg_name = "not-the-crate"
+
+            [telegram]
+            token = "123:abc"
+
+            [smtp]
+            port = 465
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(vars.get("INTEGRATIONS").unwrap(), "terminal,telegram");
+        assert_eq!(vars.get("TELEGRAM_TOKEN").unwrap(), "123:abc");
+        assert_eq!(vars.get("DISABLE_SAVING").unwrap(), "true");
+        assert_eq!(vars.number("SMTP_PORT", 25u16), Ok(465));
+        assert_eq!(vars.number("FETCH_TIMEOUT_SECS", 15u64), Ok(30));
+        assert_eq!(vars.number("ROZSDHABOT_UNSET_SECS", 15u64), Ok(15));
+        assert_eq!(vars.get("CARGO_PKG_NAME").unwrap(), env!("CARGO_PKG_NAME"));
+        assert!(vars.get("ROZSDHABOT_UNSET").is_err());
+
+        let file = "from file".to_string();
+        assert_eq!(prefer_env(Ok(String::new()), Some(&file)).unwrap(), file);
+        assert_eq!(
+            prefer_env(Ok("env".to_string()), Some(&file)).unwrap(),
+            "env"
+        );
+        assert_eq!(prefer_env(Ok(String::new()), None).unwrap(), "");
+
+        let vars = ConfigVars::parse("stagger_ms = \"soon\"").unwrap();
+        assert!(vars.number("STAGGER_MS", 1250u64).is_err());
+        assert!(ConfigVars::parse("integrations = [[\"terminal\"]]").is_err());
+        assert!(ConfigVars::parse("integrations = ").is_err());
+    }
+
+    /// Every setting in the example has to be one that is actually read.
+    #[test]
+    fn test_example_config_file() {
+        let vars = ConfigVars::parse(include_str!("../rozsdhabot.example.toml")).unwrap();
+        let source = include_str!("config.rs");
+        for key in vars.file.keys() {
+            assert!(
+                source.contains(&format!("\"{key}\"")),
+                "{key} in the example config is never read"
+            );
+        }
+        assert_eq!(vars.file["HTTP_ADDRESS"], "0.0.0.0:8080");
+        assert_eq!(vars.file["PUBLIC_URL"], "http://localhost:8080");
+    }
 }
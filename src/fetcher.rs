@@ -4,7 +4,25 @@
 // User-agent: *
 //
 // crawl-delay: 1
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
 use reqwest::Client;
+use tokio::time::Instant;
+
+use crate::metrics::METRICS;
+
+/// Minimum time between two requests to the same host, shared by every fetcher. Matches the
+/// crawl-delay in robots.txt.
+const CRAWL_DELAY: Duration = Duration::from_secs(1);
+
+/// When the next request to each host is allowed. Shared between all monitors, so search pages
+/// and listing detail pages are rate limited together.
+static NEXT_REQUEST: LazyLock<Mutex<HashMap<String, Instant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
 
 #[derive(Debug)]
 pub struct Fetcher {
@@ -13,11 +31,9 @@ pub struct Fetcher {
 
 impl Fetcher {
     /// Instead of throwing out new requests left and right.
-    pub fn new() -> Self {
-        const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
-
+    pub fn new(timeout: Duration) -> Self {
         let client = Client::builder()
-            .timeout(TIMEOUT)
+            .timeout(timeout)
             // Fake use agent to stay under the rader.
             // The agent differs beteween rozsdhabot restarts.
             .user_agent(fake_user_agent::get_rua())
@@ -28,13 +44,40 @@ impl Fetcher {
     }
 
     pub async fn fetch(&self, url: &str) -> Result<String, reqwest::Error> {
-        let response = self.client.get(url).send().await?;
-        match response.error_for_status_ref() {
-            Ok(_) => {
-                let body = response.text().await?;
-                Ok(body)
-            }
+        wait_for_turn(url).await;
+
+        // Measured without the wait, that's our own doing.
+        let start = Instant::now();
+        let response = self.client.get(url).send().await;
+        let status = response.as_ref().ok().map(|r| r.status().as_u16());
+        let result = match response.and_then(|r| r.error_for_status()) {
+            Ok(response) => response.text().await,
             Err(e) => Err(e),
-        }
+        };
+        METRICS
+            .lock()
+            .unwrap()
+            .record_fetch(start.elapsed(), status);
+        result
     }
 }
+
+/// Reserves the next free slot for the host of the URL and sleeps until it comes.
+async fn wait_for_turn(url: &str) {
+    let Some(host) = url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_string()))
+    else {
+        return;
+    };
+
+    let slot = {
+        let mut next_request = NEXT_REQUEST.lock().unwrap();
+        let now = Instant::now();
+        let slot = next_request.get(&host).copied().unwrap_or(now).max(now);
+        next_request.insert(host, slot + CRAWL_DELAY);
+        slot
+    };
+
+    tokio::time::sleep_until(slot).await;
+}
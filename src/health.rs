@@ -0,0 +1,433 @@
+//! Keeps track of how well the parsers are doing across every subscription, to notice when a
+//! marketplace changes its layout.
+//!
+//! A single broken listing is normal (e.g. a seller typing text into the price field), but if
+//! most of the listings fail to parse on most of the pages, the parser needs updating. The same
+//! goes for several subscriptions not finding any listings at all for a while.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+};
+
+use crate::parsers::{Field, ParseFailure, ParseFailureKind, ParsedPage};
+
+/// How many of the most recent pages of a parser are considered.
+const WINDOW: usize = 20;
+
+/// At least this many pages have to be seen before deciding that a parser is broken, so a single
+/// weird page doesn't trigger an alert.
+const MIN_PAGES: usize = 3;
+
+/// The parser is considered broken above this ratio of unparsable listings.
+const ALERT_RATIO: f64 = 0.5;
+
+/// The parser is considered fixed again below this ratio. Lower than [`ALERT_RATIO`] so that a
+/// ratio hovering around the threshold doesn't cause a stream of alerts.
+const RECOVERY_RATIO: f64 = 0.2;
+
+/// A subscription counts as empty after this many pages in a row without a single listing, not
+/// even a skipped or broken one. That's what a total layout change looks like, the parser doesn't
+/// find the listings at all.
+const EMPTY_PAGES: usize = 3;
+
+/// The parser is considered broken if at least this many of its recent subscriptions are empty,
+/// and none of them found anything. Narrow searches are often empty on their own, but not all of
+/// them at once while the others find nothing either. With fewer subscriptions, empty pages
+/// alone don't raise an alert.
+const EMPTY_SUBSCRIPTIONS: usize = 3;
+
+/// Example values included in the alert for each field.
+const MAX_SAMPLES: usize = 3;
+
+/// Shared by every monitor, like the rate limiter of the fetcher.
+pub static PARSER_HEALTH: LazyLock<Mutex<ParserHealth>> =
+    LazyLock::new(|| Mutex::new(ParserHealth::default()));
+
+#[derive(Default)]
+pub struct ParserHealth {
+    parsers: HashMap<&'static str, ParserWindow>,
+    /// Pages that trigger an alert are saved here. Nothing is saved if unset.
+    dump_dir: Option<PathBuf>,
+}
+
+#[derive(Default)]
+struct ParserWindow {
+    pages: VecDeque<PageReport>,
+    /// Pages in a row without any listings, by subscription.
+    empty_streaks: HashMap<u64, usize>,
+    /// Set after an alert is sent, so that there is only one alert per breakage.
+    alerted: bool,
+}
+
+struct PageReport {
+    subscription_id: u64,
+    parsed: usize,
+    failures: Vec<ParseFailure>,
+}
+
+/// Sent to the admin channel when a parser breaks.
+#[derive(Debug)]
+pub struct HealthAlert {
+    pub parser: &'static str,
+    /// Ratio of unparsable listings in the window.
+    pub failure_ratio: f64,
+    /// The number of subscriptions without any listings on their last [`EMPTY_PAGES`] pages, if
+    /// that's why the alert was sent.
+    pub empty_subscriptions: usize,
+    /// Number of distinct subscriptions with broken pages.
+    pub subscriptions: usize,
+    pub fields: Vec<FieldReport>,
+    /// Where the page that triggered the alert was saved, see [`dump_page`].
+    pub dump_path: Option<PathBuf>,
+}
+
+/// How a parser is doing, for `/status`.
+#[derive(Debug)]
+pub struct ParserSummary {
+    pub parser: &'static str,
+    /// Pages in the window.
+    pub pages: usize,
+    pub failure_ratio: f64,
+    /// An alert was sent and the parser hasn't recovered since.
+    pub broken: bool,
+}
+
+#[derive(Debug)]
+pub struct FieldReport {
+    pub field: Field,
+    pub kind: ParseFailureKind,
+    pub count: usize,
+    pub samples: Vec<String>,
+}
+
+impl ParserWindow {
+    /// Ratio of listings that couldn't be parsed because of missing or invalid fields. Skipped
+    /// listings are not counted, those are skipped on purpose.
+    fn failure_ratio(&self) -> f64 {
+        let (parsed, failed) = self.pages.iter().fold((0, 0), |(parsed, failed), page| {
+            (
+                parsed + page.parsed,
+                failed + unparsable(&page.failures).count(),
+            )
+        });
+        match parsed + failed {
+            0 => 0.0,
+            total => failed as f64 / total as f64,
+        }
+    }
+
+    /// The number of subscriptions in the window that are on an empty streak of at least
+    /// [`EMPTY_PAGES`], or 0 if any subscription in the window found listings on its last page.
+    fn empty_subscriptions(&self) -> usize {
+        let streaks: Vec<usize> = self
+            .pages
+            .iter()
+            .map(|p| p.subscription_id)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .map(|id| self.empty_streaks.get(&id).copied().unwrap_or(0))
+            .collect();
+        match streaks.contains(&0) {
+            true => 0,
+            false => streaks.iter().filter(|s| **s >= EMPTY_PAGES).count(),
+        }
+    }
+
+    fn field_reports(&self) -> Vec<FieldReport> {
+        let mut reports: Vec<FieldReport> = Vec::new();
+        for failure in self.pages.iter().flat_map(|p| unparsable(&p.failures)) {
+            let report = match reports
+                .iter_mut()
+                .find(|r| r.field == failure.field && r.kind == failure.kind)
+            {
+                Some(report) => report,
+                None => {
+                    reports.push(FieldReport {
+                        field: failure.field,
+                        kind: failure.kind,
+                        count: 0,
+                        samples: Vec::new(),
+                    });
+                    reports.last_mut().unwrap()
+                }
+            };
+            report.count += 1;
+            if let Some(value) = &failure.value
+                && report.samples.len() < MAX_SAMPLES
+                && !report.samples.contains(value)
+            {
+                report.samples.push(value.clone());
+            }
+        }
+        reports.sort_by_key(|r| std::cmp::Reverse(r.count));
+        reports
+    }
+}
+
+impl ParserHealth {
+    pub fn set_dump_dir(&mut self, dump_dir: PathBuf) {
+        self.dump_dir = Some(dump_dir);
+    }
+
+    /// Where pages that trigger an alert should be saved, see [`dump_page`].
+    pub fn dump_dir(&self) -> Option<PathBuf> {
+        self.dump_dir.clone()
+    }
+
+    /// Every parser that has parsed a page, by name.
+    pub fn summary(&self) -> Vec<ParserSummary> {
+        let mut summary: Vec<ParserSummary> = self
+            .parsers
+            .iter()
+            .map(|(parser, window)| ParserSummary {
+                parser,
+                pages: window.pages.len(),
+                failure_ratio: window.failure_ratio(),
+                broken: window.alerted,
+            })
+            .collect();
+        summary.sort_by_key(|s| s.parser);
+        summary
+    }
+
+    /// Records the result of parsing a page. Returns an alert if this page made the parser look
+    /// broken. Only one alert is returned until the parser recovers. The alert has no
+    /// `dump_path` yet, the page is saved by the caller, outside the lock.
+    pub fn record(
+        &mut self,
+        parser: &'static str,
+        subscription_id: u64,
+        page: &ParsedPage,
+    ) -> Option<HealthAlert> {
+        let window = self.parsers.entry(parser).or_default();
+        window.pages.push_back(PageReport {
+            subscription_id,
+            parsed: page.listings.len(),
+            failures: unparsable(&page.failures).cloned().collect(),
+        });
+        if window.pages.len() > WINDOW {
+            window.pages.pop_front();
+        }
+        let streak = window.empty_streaks.entry(subscription_id).or_default();
+        match page.listings.is_empty() && page.failures.is_empty() {
+            true => *streak += 1,
+            false => *streak = 0,
+        }
+
+        let failure_ratio = window.failure_ratio();
+        let empty_subscriptions = window.empty_subscriptions();
+        if window.alerted {
+            if failure_ratio < RECOVERY_RATIO && empty_subscriptions == 0 {
+                tracing::info!("Parser {parser} recovered, failure ratio is {failure_ratio:.2}");
+                window.alerted = false;
+            }
+            return None;
+        }
+        let empty = empty_subscriptions >= EMPTY_SUBSCRIPTIONS;
+        if !empty && (window.pages.len() < MIN_PAGES || failure_ratio < ALERT_RATIO) {
+            return None;
+        }
+
+        window.alerted = true;
+        let subscriptions = window
+            .pages
+            .iter()
+            .filter(|p| {
+                p.failures.len() as f64 >= ALERT_RATIO * (p.parsed + p.failures.len()) as f64
+            })
+            .map(|p| p.subscription_id)
+            .collect::<HashSet<_>>()
+            .len();
+        let fields = window.field_reports();
+
+        Some(HealthAlert {
+            parser,
+            failure_ratio,
+            empty_subscriptions: if empty { empty_subscriptions } else { 0 },
+            subscriptions,
+            fields,
+            dump_path: None,
+        })
+    }
+}
+
+/// Saves the page that triggered an alert for offline debugging. Failures are logged.
+pub async fn dump_page(dir: &Path, parser: &str, body: &str) -> Option<PathBuf> {
+    let name = format!(
+        "{}-{}.html",
+        parser.to_lowercase(),
+        chrono::Utc::now().format("%Y%m%d-%H%M%SZ")
+    );
+    let path = dir.join(name);
+
+    let result = match tokio::fs::create_dir_all(dir).await {
+        Ok(()) => tokio::fs::write(&path, body).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => Some(path),
+        Err(e) => {
+            tracing::error!("Failed to save failing page to {path:?}: {e}");
+            None
+        }
+    }
+}
+
+fn unparsable(failures: &[ParseFailure]) -> impl Iterator<Item = &ParseFailure> {
+    failures
+        .iter()
+        .filter(|f| f.kind != ParseFailureKind::Skipped)
+}
+
+impl std::fmt::Display for HealthAlert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "⚠️ The {} parser looks broken, the site's layout may have changed.",
+            self.parser
+        )?;
+        match self.empty_subscriptions {
+            0 => writeln!(
+                f,
+                "{:.0}% of recent listings could not be parsed, across {} subscriptions.",
+                self.failure_ratio * 100.0,
+                self.subscriptions
+            )?,
+            subscriptions => writeln!(
+                f,
+                "{subscriptions} subscriptions found no listings on their last {EMPTY_PAGES} pages, \
+                 and none of the others found any either.",
+            )?,
+        }
+        for report in &self.fields {
+            write!(f, "- {} {:?}: {}", report.field, report.kind, report.count)?;
+            if !report.samples.is_empty() {
+                write!(f, " (e.g. {})", report.samples.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        if let Some(path) = &self.dump_path {
+            writeln!(f, "The page was saved to {}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Listing, ScrapeMetadata};
+
+    fn page(parsed: usize, failures: Vec<ParseFailure>) -> ParsedPage {
+        ParsedPage {
+            metadata: ScrapeMetadata {
+                name: None,
+                category: None,
+                min_price: None,
+                max_price: None,
+            },
+            listings: vec![Listing::dummy(); parsed],
+            failures,
+        }
+    }
+
+    fn invalid_price(value: &str) -> ParseFailure {
+        ParseFailure {
+            field: Field::Price,
+            kind: ParseFailureKind::Invalid,
+            value: Some(value.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_single_alert_per_breakage() {
+        let mut health = ParserHealth::default();
+
+        // A few bad listings are normal.
+        for id in 0..5 {
+            let healthy = page(20, vec![invalid_price("hívj")]);
+            assert!(health.record("Test", id, &healthy).is_none());
+        }
+
+        let broken = || page(1, vec![invalid_price("12.000,-"), invalid_price("1 2 3")]);
+        let alert = (0..20)
+            .filter_map(|id| health.record("Test", id % 2, &broken()))
+            .collect::<Vec<_>>();
+        assert_eq!(alert.len(), 1);
+        assert_eq!(alert[0].subscriptions, 2);
+        assert_eq!(alert[0].fields.len(), 1);
+        assert_eq!(alert[0].fields[0].field, Field::Price);
+        assert!(alert[0].fields[0].samples.contains(&"12.000,-".to_string()));
+        assert!(health.summary()[0].broken);
+
+        // Skipped listings don't count as failures, so this is a healthy page.
+        for id in 0..WINDOW as u64 {
+            let skipped = ParseFailure {
+                field: Field::Date,
+                kind: ParseFailureKind::Skipped,
+                value: None,
+            };
+            assert!(
+                health
+                    .record("Test", id, &page(20, vec![skipped]))
+                    .is_none()
+            );
+        }
+        assert!(!health.parsers["Test"].alerted);
+
+        // After recovering, a new breakage is alerted again.
+        let alerts = (0..WINDOW as u64)
+            .filter_map(|id| health.record("Test", id, &broken()))
+            .count();
+        assert_eq!(alerts, 1);
+    }
+
+    #[test]
+    fn test_alert_on_empty_pages() {
+        let mut health = ParserHealth::default();
+        let empty = || page(0, Vec::new());
+
+        // Narrow searches without results, while another search finds listings.
+        for round in 0..2 * WINDOW as u64 {
+            let id = round % 4;
+            let page = if id == 3 {
+                page(20, Vec::new())
+            } else {
+                empty()
+            };
+            assert!(health.record("Test", id, &page).is_none());
+        }
+
+        // Nothing found anywhere: the layout changed so much that no listing is recognized.
+        let alerts: Vec<HealthAlert> = (0..WINDOW as u64)
+            .filter_map(|round| health.record("Test", round % 4, &empty()))
+            .collect();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].empty_subscriptions, 3);
+        assert!(
+            alerts[0]
+                .to_string()
+                .contains("3 subscriptions found no listings")
+        );
+
+        assert!(health.record("Test", 3, &page(20, Vec::new())).is_none());
+        assert!(!health.parsers["Test"].alerted);
+    }
+
+    #[tokio::test]
+    async fn test_dump_page() {
+        let dir = std::env::temp_dir().join(format!("rozsdhabot-dump-{}", std::process::id()));
+        let path = dump_page(&dir, "Test", "<html></html>").await.unwrap();
+        assert!(
+            path.file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .ends_with("Z.html")
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "<html></html>");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
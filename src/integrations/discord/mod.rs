@@ -1,3 +1,5 @@
+// Slash commands are not registered yet, messages are read as plain text instead.
+#[allow(dead_code)]
 mod commands;
 
 use async_trait::async_trait;
@@ -6,17 +8,21 @@ use std::sync::Arc;
 use crate::{
     AppCtx,
     integrations::{
-        Controller, Notifier,
+        Controller, Notifier, format_details,
         message_handler::{IncomingMessage, handle_message},
+        split_message,
     },
-    models::{ChannelId, Listing, Subscription},
-    parsers::ScrapeMetadata,
+    models::{ChannelId, HeldListing, Listing, ScrapeMetadata, Subscription, format_timestamp},
+    status::ConnectionState,
 };
 use serenity::{
     Client,
-    all::{Context, EventHandler, GatewayIntents, Http},
+    all::{ConnectionStage, Context, EventHandler, GatewayIntents, Http, ShardManager},
 };
 
+/// Discord rejects longer messages.
+const MAX_MESSAGE_LENGTH: usize = 2000;
+
 pub struct DiscordController {
     token: String,
 }
@@ -31,6 +37,10 @@ impl DiscordController {
 
 #[async_trait]
 impl Controller for DiscordController {
+    fn connection_name(&self) -> Option<&'static str> {
+        Some("discord")
+    }
+
     async fn start(self: Box<Self>, context: AppCtx) -> () {
         let intents = GatewayIntents::GUILDS
             | GatewayIntents::GUILD_MESSAGES
@@ -44,10 +54,52 @@ impl Controller for DiscordController {
 
         {
             let mut data = client.data.write().await;
-            data.insert::<AppCtxKey>(context);
+            data.insert::<AppCtxKey>(context.clone());
         }
 
-        client.start().await;
+        let watcher = tokio::spawn(watch_connection(
+            client.shard_manager.clone(),
+            context.clone(),
+        ));
+        let error = match client.start().await {
+            Ok(()) => "client stopped".to_string(),
+            Err(e) => {
+                tracing::error!("Discord client stopped: {e}");
+                e.to_string()
+            }
+        };
+        watcher.abort();
+        context
+            .status
+            .set_connection("discord", ConnectionState::Disconnected, Some(error));
+    }
+}
+
+/// Periodically reports the state of the gateway connection. Serenity reconnects by itself, but
+/// a connection that never comes back should show up in the health checks.
+async fn watch_connection(shard_manager: Arc<ShardManager>, context: AppCtx) {
+    const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        let stages: Vec<ConnectionStage> = shard_manager
+            .runners
+            .lock()
+            .await
+            .values()
+            .map(|runner| runner.stage)
+            .collect();
+
+        let (state, error) = match stages.iter().find(|s| **s != ConnectionStage::Connected) {
+            _ if stages.is_empty() => (ConnectionState::Connecting, None),
+            None => (ConnectionState::Connected, None),
+            Some(stage) => (
+                ConnectionState::Disconnected,
+                Some(format!("gateway is {stage}")),
+            ),
+        };
+        context.status.set_connection("discord", state, error);
     }
 }
 
@@ -70,6 +122,10 @@ impl DiscordNotifier {
     ) -> String {
         use teloxide::utils::markdown;
         let price = listing.price;
+        let bumped = match listing.bumped {
+            true => " (bumped)",
+            false => "",
+        };
         let link = markdown::link(&listing.url, markdown::escape(&listing.title).as_str());
         let sub_title =
             markdown::escape(sub.name.clone().unwrap_or("(unnamed)".to_string()).as_str());
@@ -82,9 +138,29 @@ impl DiscordNotifier {
         // );
         //
         let id = sub.id.to_string();
-        let seller_name = markdown::escape(listing.seller_name.as_str());
-        let seller_ratings = markdown::escape(listing.seller_ratings.to_string().as_str());
+        let business = match listing.seller_is_business {
+            true => " \\(business\\)",
+            false => "",
+        };
+        // Not every site shows the seller in search results.
+        let seller = match (listing.seller_name.is_empty(), listing.seller_is_business) {
+            (true, false) => String::new(),
+            (true, true) => "\\- Business seller".to_string(),
+            (false, _) => format!(
+                "\\- {} \\(\\+{}\\){business}",
+                markdown::escape(listing.seller_name.as_str()),
+                markdown::escape(listing.seller_ratings.to_string().as_str())
+            ),
+        };
         let cities = markdown::escape(listing.cities.join(", ").as_str());
+        let date = match &listing.date {
+            Some(date) => format!(" \\- {}", markdown::escape(&format_timestamp(date))),
+            None => String::new(),
+        };
+        let details = match &listing.details {
+            Some(details) => format_details(details),
+            None => String::new(),
+        };
 
         let pricerange = markdown::escape(
             match (metadata.min_price, metadata.max_price) {
@@ -99,9 +175,10 @@ impl DiscordNotifier {
         format!(
             "
 ## New: {link}
-## {price} Ft
-{cities}
-\\- {seller_name} \\(\\+{seller_ratings}\\)
+## {price}{bumped}
+{cities}{date}
+{seller}
+{details}
 
 -# From subscription:
 -# {sub_title} \\({id}\\):
@@ -109,6 +186,25 @@ impl DiscordNotifier {
 ",
         )
     }
+
+    /// Every held listing is listed, split into as many messages as needed.
+    fn format_catch_up(&self, held: &[HeldListing]) -> Vec<String> {
+        use teloxide::utils::markdown;
+
+        let header = format!("## {} new listings you missed:", held.len());
+        let lines = held.iter().map(|entry| {
+            let link = markdown::link(
+                &entry.listing.url,
+                markdown::escape(&entry.listing.title).as_str(),
+            );
+            format!(
+                "{} \\- {link} \\({}\\)",
+                entry.listing.price, entry.subscription.id
+            )
+        });
+
+        split_message(std::iter::once(header).chain(lines), MAX_MESSAGE_LENGTH)
+    }
 }
 
 #[async_trait]
@@ -140,7 +236,40 @@ impl Notifier for DiscordNotifier {
             .map_err(|e| e.to_string())
             .map(|_| ())
     }
-    async fn send_coconut(&self, channel_id: ChannelId) -> Result<(), String> {
+
+    async fn notify_catch_up(
+        &self,
+        held: &[HeldListing],
+        channel_id: ChannelId,
+    ) -> Result<(), String> {
+        let channel = match channel_id {
+            ChannelId::Discord { channel } => channel,
+            _ => return Err("Invalid channel ID: expected Discord channel.".to_string()),
+        };
+
+        for message in self.format_catch_up(held) {
+            channel
+                .say(self.http.clone(), message)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    async fn send_message(&self, text: &str, channel_id: ChannelId) -> Result<(), String> {
+        let channel = match channel_id {
+            ChannelId::Discord { channel } => channel,
+            _ => return Err("Invalid channel ID: expected Discord channel.".to_string()),
+        };
+
+        channel
+            .say(self.http.clone(), text)
+            .await
+            .map_err(|e| e.to_string())
+            .map(|_| ())
+    }
+
+    async fn send_coconut(&self, _channel_id: ChannelId) -> Result<(), String> {
         todo!()
     }
 }
@@ -217,10 +346,10 @@ impl EventHandler for Handler {
             if let Err(why) = channel.say(&ctx.http, e).await {
                 tracing::error!("Error sending message: {why:?}");
             }
-        } else if let Ok(Some(reply)) = reply {
-            if let Err(why) = channel.say(&ctx.http, reply).await {
-                tracing::error!("Error sending message: {why:?}");
-            }
+        } else if let Ok(Some(reply)) = reply
+            && let Err(why) = channel.say(&ctx.http, reply).await
+        {
+            tracing::error!("Error sending message: {why:?}");
         }
     }
 
@@ -0,0 +1,364 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{Mailbox, MultiPart},
+    transport::smtp::authentication::Credentials,
+};
+
+use crate::{
+    integrations::{DESCRIPTION_EXCERPT_LENGTH, Notifier, escape_html},
+    models::{ChannelId, HeldListing, Listing, ScrapeMetadata, Subscription, format_timestamp},
+};
+
+/// How the connection to the SMTP server is secured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SmtpTls {
+    /// Plain connection upgraded with STARTTLS. Port 587 by default.
+    StartTls,
+    /// TLS from the start. Port 465 by default.
+    Tls,
+    /// Unencrypted. Only meant for local relays and testing. Port 25 by default.
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SmtpSettings {
+    pub host: String,
+    /// The default port of the TLS mode is used if unset.
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub tls: SmtpTls,
+    /// The sender of the emails, e.g. `rozsdhabot <bot@example.com>`.
+    pub from: String,
+    /// If set, new listings are held and sent as a single digest email this often, instead of one
+    /// email per listing. Held listings are saved with the channel, like during quiet hours.
+    pub digest_interval: Option<Duration>,
+}
+
+/// Sends notifications as HTML emails.
+pub struct EmailNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    digest_interval: Option<Duration>,
+}
+
+impl EmailNotifier {
+    pub fn new(settings: &SmtpSettings) -> Result<Arc<Self>, String> {
+        let builder = match settings.tls {
+            SmtpTls::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host),
+            SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                &settings.host,
+            )),
+        }
+        .map_err(|e| format!("Invalid SMTP host: {e}"))?;
+
+        let builder = match settings.port {
+            Some(port) => builder.port(port),
+            None => builder,
+        };
+        let builder = match (&settings.username, &settings.password) {
+            (Some(username), Some(password)) => {
+                builder.credentials(Credentials::new(username.clone(), password.clone()))
+            }
+            _ => builder,
+        };
+
+        let from = settings
+            .from
+            .parse::<Mailbox>()
+            .map_err(|e| format!("Invalid sender address '{}': {e}", settings.from))?;
+
+        Ok(Arc::new(Self {
+            transport: builder.build(),
+            from,
+            digest_interval: settings.digest_interval,
+        }))
+    }
+
+    async fn send(
+        &self,
+        address: &str,
+        subject: &str,
+        plain: String,
+        html: String,
+    ) -> Result<(), String> {
+        let to = address
+            .parse::<Mailbox>()
+            .map_err(|e| format!("Invalid email address '{address}': {e}"))?;
+
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .multipart(MultiPart::alternative_plain_html(plain, html))
+            .map_err(|e| format!("Failed to build email: {e}"))?;
+
+        self.transport
+            .send(email)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Failed to send email: {e}"))
+    }
+
+    async fn send_digest(&self, address: &str, held: &[HeldListing]) -> Result<(), String> {
+        let subject = format!("{} new listings", held.len());
+        let plain = held
+            .iter()
+            .map(|h| {
+                format!(
+                    "{} - {} {}",
+                    h.listing.title, h.listing.price, h.listing.url
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.send(address, &subject, plain, format_digest(held))
+            .await
+    }
+}
+
+fn email_address(channel_id: &ChannelId) -> Result<&str, String> {
+    match channel_id {
+        ChannelId::Email { address } => Ok(address),
+        _ => Err("Invalid channel ID: expected email address.".to_string()),
+    }
+}
+
+/// Renders a listing as an HTML fragment.
+fn format_listing(subscription: &Subscription, listing: &Listing) -> String {
+    let image = match &listing.img_url {
+        Some(url) => format!(
+            r#"<img src="{}" alt="" style="max-width:200px;max-height:200px;float:right;margin-left:12px">"#,
+            escape_html(url)
+        ),
+        None => String::new(),
+    };
+    let bumped = match listing.bumped {
+        true => " (bumped)",
+        false => "",
+    };
+    let mut facts = vec![listing.cities.join(", ")];
+    if let Some(date) = &listing.date {
+        facts.push(format_timestamp(date));
+    }
+    if !listing.seller_name.is_empty() {
+        facts.push(format!(
+            "{} (+{})",
+            listing.seller_name, listing.seller_ratings
+        ));
+    }
+    if listing.seller_is_business {
+        facts.push("business seller".to_string());
+    }
+    let description = match &listing.details {
+        Some(details) if !details.description.is_empty() => {
+            format!(
+                "<p><i>{}</i></p>",
+                escape_html(&details.description_excerpt(DESCRIPTION_EXCERPT_LENGTH))
+            )
+        }
+        _ => String::new(),
+    };
+
+    format!(
+        r#"<div style="overflow:hidden;margin-bottom:16px">{image}<h3><a href="{url}">{title}</a></h3><p><b>{price}{bumped}</b></p><p>{facts}</p>{description}<p style="color:#888">From subscription: {name} ({id})</p></div>"#,
+        url = escape_html(&listing.url),
+        title = escape_html(&listing.title),
+        price = escape_html(&listing.price.to_string()),
+        facts = escape_html(&facts.join(" | ")),
+        name = escape_html(subscription.name.as_deref().unwrap_or("(unnamed)")),
+        id = subscription.id,
+    )
+}
+
+/// Renders a digest of several listings as an HTML document.
+fn format_digest(held: &[HeldListing]) -> String {
+    let body: String = held
+        .iter()
+        .map(|h| format_listing(&h.subscription, &h.listing))
+        .collect();
+    format!("<html><body>{body}</body></html>")
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier {
+    async fn notify_new_listing(
+        &self,
+        subscription: &Subscription,
+        _metadata: &ScrapeMetadata,
+        listing: &Listing,
+        channel_id: ChannelId,
+    ) -> Result<(), String> {
+        let address = email_address(&channel_id)?;
+        let subject = format!("New listing: {} ({})", listing.title, listing.price);
+        let plain = format!("{}\n{}\n{}", listing.title, listing.price, listing.url);
+        let html = format!(
+            "<html><body>{}</body></html>",
+            format_listing(subscription, listing)
+        );
+        self.send(address, &subject, plain, html).await
+    }
+
+    fn digest_interval(&self) -> Option<Duration> {
+        self.digest_interval
+    }
+
+    async fn notify_catch_up(
+        &self,
+        held: &[HeldListing],
+        channel_id: ChannelId,
+    ) -> Result<(), String> {
+        self.send_digest(email_address(&channel_id)?, held).await
+    }
+
+    async fn send_message(&self, text: &str, channel_id: ChannelId) -> Result<(), String> {
+        let address = email_address(&channel_id)?;
+        let html = format!("<html><body><pre>{}</pre></body></html>", escape_html(text));
+        self.send(address, "Message from rozsdhabot", text.to_string(), html)
+            .await
+    }
+
+    async fn send_coconut(&self, _channel_id: ChannelId) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// A minimal SMTP server that accepts a single email and returns its data.
+    async fn smtp_sink() -> (u16, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            write.write_all(b"220 sink ready\r\n").await.unwrap();
+
+            let mut data = String::new();
+            let mut in_data = false;
+            while let Some(line) = lines.next_line().await.unwrap() {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        write.write_all(b"250 OK\r\n").await.unwrap();
+                    } else {
+                        data += &line;
+                        data += "\n";
+                    }
+                    continue;
+                }
+                let reply: &[u8] = match line.to_uppercase().split_whitespace().next() {
+                    Some("EHLO") | Some("HELO") => b"250 sink\r\n",
+                    Some("DATA") => {
+                        in_data = true;
+                        b"354 go ahead\r\n"
+                    }
+                    Some("QUIT") => {
+                        write.write_all(b"221 bye\r\n").await.unwrap();
+                        break;
+                    }
+                    _ => b"250 OK\r\n",
+                };
+                write.write_all(reply).await.unwrap();
+            }
+            data
+        });
+
+        (port, handle)
+    }
+
+    #[test]
+    fn test_format_listing_escapes_html() {
+        let listing = Listing {
+            title: "RX 6700 <XT> & more".to_string(),
+            img_url: Some("https://example.com/a.jpg".to_string()),
+            ..Listing::dummy()
+        };
+        let subscription = Subscription {
+            name: Some("<b>gpu</b>".to_string()),
+            ..Subscription::dummy()
+        };
+        let html = format_listing(&subscription, &listing);
+        assert!(html.contains("RX 6700 &lt;XT&gt; &amp; more"));
+        assert!(html.contains("&lt;b&gt;gpu&lt;/b&gt;"));
+        assert!(html.contains(r#"<img src="https://example.com/a.jpg""#));
+    }
+
+    #[test]
+    fn test_format_digest_has_every_listing() {
+        let held: Vec<HeldListing> = (0..100)
+            .map(|id| HeldListing {
+                subscription: Subscription::dummy(),
+                metadata: ScrapeMetadata {
+                    name: None,
+                    category: None,
+                    min_price: None,
+                    max_price: None,
+                },
+                listing: Listing {
+                    id,
+                    title: format!("listing {id}"),
+                    ..Listing::dummy()
+                },
+            })
+            .collect();
+        let html = format_digest(&held);
+        assert!(html.contains("listing 0<"));
+        assert!(html.contains("listing 99<"));
+        assert!(!html.contains("more."));
+    }
+
+    #[tokio::test]
+    async fn test_send_to_smtp_sink() {
+        let (port, sink) = smtp_sink().await;
+        let notifier = EmailNotifier::new(&SmtpSettings {
+            host: "127.0.0.1".to_string(),
+            port: Some(port),
+            username: None,
+            password: None,
+            tls: SmtpTls::None,
+            from: "rozsdhabot <bot@example.com>".to_string(),
+            digest_interval: None,
+        })
+        .unwrap();
+
+        let listing = Listing {
+            title: "Sapphire Pulse".to_string(),
+            ..Listing::dummy()
+        };
+        let metadata = ScrapeMetadata {
+            name: None,
+            category: None,
+            min_price: None,
+            max_price: None,
+        };
+        let channel = ChannelId::Email {
+            address: "user@example.com".to_string(),
+        };
+        notifier
+            .notify_new_listing(&Subscription::dummy(), &metadata, &listing, channel)
+            .await
+            .unwrap();
+        drop(notifier);
+
+        let data = sink.await.unwrap();
+        assert!(data.contains("To: user@example.com"));
+        assert!(data.contains("Subject: New listing: Sapphire Pulse (Free)"));
+        assert!(data.contains("text/html"));
+    }
+}
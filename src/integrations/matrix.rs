@@ -0,0 +1,567 @@
+//! Matrix integration, talking to the homeserver through the client-server HTTP API.
+//!
+//! The bot logs in with an access token, joins every room it is invited to, and treats text
+//! messages in joined rooms the same way as the other controllers.
+
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use url::Url;
+
+use crate::{
+    AppCtx,
+    integrations::{
+        Controller, DESCRIPTION_EXCERPT_LENGTH, MAX_CATCH_UP_LISTINGS, Notifier, catch_up_header,
+        escape_html,
+        message_handler::{IncomingMessage, handle_message},
+    },
+    models::{
+        ChannelId, HeldListing, Listing, OwnerId, ScrapeMetadata, Subscription, format_timestamp,
+    },
+    status::ConnectionState,
+};
+
+/// How long a sync request waits for new events on the server.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Waited after a failed sync, so that an unreachable homeserver isn't hammered.
+const SYNC_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+pub struct MatrixIntegration {
+    client: reqwest::Client,
+    homeserver: Url,
+    access_token: String,
+    /// Transaction IDs have to be unique per access token, so that retried requests are not
+    /// sent twice.
+    next_transaction: Arc<AtomicU64>,
+}
+
+#[derive(Deserialize)]
+struct SyncResponse {
+    next_batch: String,
+    #[serde(default)]
+    rooms: SyncRooms,
+}
+
+#[derive(Deserialize, Default)]
+struct SyncRooms {
+    #[serde(default)]
+    join: HashMap<String, JoinedRoom>,
+    #[serde(default)]
+    invite: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct JoinedRoom {
+    #[serde(default)]
+    timeline: Timeline,
+}
+
+#[derive(Deserialize, Default)]
+struct Timeline {
+    #[serde(default)]
+    events: Vec<RoomEvent>,
+}
+
+#[derive(Deserialize)]
+struct RoomEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    event_id: String,
+    sender: String,
+    #[serde(default)]
+    content: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct WhoAmI {
+    user_id: String,
+}
+
+impl MatrixIntegration {
+    pub fn new(homeserver: &str, access_token: String) -> Result<Self, String> {
+        let homeserver =
+            Url::parse(homeserver).map_err(|e| format!("Invalid Matrix homeserver URL: {e}"))?;
+        // e.g. `matrix.example.org:8448` parses with `matrix.example.org` as the scheme.
+        if !matches!(homeserver.scheme(), "http" | "https") {
+            return Err(format!(
+                "Invalid Matrix homeserver URL: '{homeserver}'. It has to start with https://"
+            ));
+        }
+
+        Ok(Self {
+            client: reqwest::Client::builder()
+                // Sync requests are held open by the server.
+                .timeout(SYNC_TIMEOUT + Duration::from_secs(15))
+                .build()
+                // unsafe for now
+                .unwrap(),
+            homeserver,
+            access_token,
+            next_transaction: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Builds an API URL from path segments. Segments are percent-encoded where needed.
+    fn endpoint(&self, segments: &[&str]) -> Url {
+        let mut url = self.homeserver.clone();
+        url.path_segments_mut()
+            // Only fails for URLs that can't be a base. HTTP(S) URLs always can, and `new` rejects
+            // every other scheme.
+            .expect("homeserver URL can't be a base")
+            .pop_if_empty()
+            .extend(["_matrix", "client", "v3"])
+            .extend(segments);
+        url
+    }
+
+    async fn whoami(&self) -> Result<String, String> {
+        let response = self
+            .client
+            .get(self.endpoint(&["account", "whoami"]))
+            .bearer_auth(&self.access_token)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Failed to reach homeserver: {e}"))?;
+        response
+            .json::<WhoAmI>()
+            .await
+            .map(|w| w.user_id)
+            .map_err(|e| format!("Invalid whoami response: {e}"))
+    }
+
+    async fn sync(&self, since: Option<&str>, timeout: Duration) -> Result<SyncResponse, String> {
+        let mut url = self.endpoint(&["sync"]);
+        url.query_pairs_mut()
+            .append_pair("timeout", &timeout.as_millis().to_string());
+        if let Some(since) = since {
+            url.query_pairs_mut().append_pair("since", since);
+        }
+
+        self.client
+            .get(url)
+            .bearer_auth(&self.access_token)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Sync failed: {e}"))?
+            .json::<SyncResponse>()
+            .await
+            .map_err(|e| format!("Invalid sync response: {e}"))
+    }
+
+    async fn join(&self, room_id: &str) -> Result<(), String> {
+        self.client
+            .post(self.endpoint(&["rooms", room_id, "join"]))
+            .bearer_auth(&self.access_token)
+            .json(&json!({}))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map(|_| ())
+            .map_err(|e| format!("Failed to join room {room_id}: {e}"))
+    }
+
+    /// Sends a message with a plain text body and an HTML body for clients that support it.
+    /// Notices are meant for bots, clients don't notify for them by default.
+    async fn send(
+        &self,
+        room_id: &str,
+        plain: &str,
+        html: &str,
+        notice: bool,
+    ) -> Result<(), String> {
+        let transaction = format!(
+            "rozsdhabot-{}-{}",
+            chrono::Utc::now().timestamp_millis(),
+            self.next_transaction.fetch_add(1, Ordering::Relaxed)
+        );
+        let content = json!({
+            "msgtype": if notice { "m.notice" } else { "m.text" },
+            "body": plain,
+            "format": "org.matrix.custom.html",
+            "formatted_body": html,
+        });
+
+        self.client
+            .put(self.endpoint(&["rooms", room_id, "send", "m.room.message", &transaction]))
+            .bearer_auth(&self.access_token)
+            .json(&content)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map(|_| ())
+            .map_err(|e| format!("Failed to send message: {e}"))
+    }
+
+    fn format_notification(
+        &self,
+        subscription: &Subscription,
+        metadata: &ScrapeMetadata,
+        listing: &Listing,
+    ) -> String {
+        let bumped = match listing.bumped {
+            true => " (bumped)",
+            false => "",
+        };
+        let mut facts = vec![listing.cities.join(", ")];
+        if let Some(date) = &listing.date {
+            facts.push(format_timestamp(date));
+        }
+        if !listing.seller_name.is_empty() {
+            facts.push(format!(
+                "{} (+{})",
+                listing.seller_name, listing.seller_ratings
+            ));
+        }
+        if listing.seller_is_business {
+            facts.push("business seller".to_string());
+        }
+        let details = match &listing.details {
+            Some(details) if !details.description.is_empty() => format!(
+                "<br><i>{}</i>",
+                escape_html(&details.description_excerpt(DESCRIPTION_EXCERPT_LENGTH))
+            ),
+            _ => String::new(),
+        };
+        let pricerange = match (metadata.min_price, metadata.max_price) {
+            (Some(min), Some(max)) => format!(" between {min:.0} - {max:.0} Ft"),
+            (Some(min), None) => format!(" above {min:.0} Ft"),
+            (None, Some(max)) => format!(" under {max:.0} Ft"),
+            (None, None) => String::new(),
+        };
+
+        format!(
+            r##"<b>New: <a href="{url}">{title}</a></b><br><b>{price}{bumped}</b><br>{facts}{details}<br><font color="#888888">From subscription: <a href="{sub_url}">{sub_name}</a> ({id}){pricerange}</font>"##,
+            url = escape_html(&listing.url),
+            title = escape_html(&listing.title),
+            price = escape_html(&listing.price.to_string()),
+            facts = escape_html(&facts.join(" | ")),
+            sub_url = escape_html(&subscription.url),
+            sub_name = escape_html(subscription.name.as_deref().unwrap_or("(unnamed)")),
+            id = subscription.id,
+            pricerange = escape_html(&pricerange),
+        )
+    }
+
+    /// Every held listing, as messages of at most `MAX_CATCH_UP_LISTINGS` listings. Returns the
+    /// plain text and the HTML of each.
+    fn format_catch_up(&self, held: &[HeldListing]) -> Vec<(String, String)> {
+        let parts = held.len().div_ceil(MAX_CATCH_UP_LISTINGS);
+        held.chunks(MAX_CATCH_UP_LISTINGS)
+            .enumerate()
+            .map(|(index, chunk)| {
+                let header = catch_up_header(held.len(), index + 1, parts);
+                let mut html = format!("<b>{}:</b>", escape_html(&header));
+                for entry in chunk {
+                    html += &format!(
+                        r#"<br>{} - <a href="{}">{}</a> ({})"#,
+                        escape_html(&entry.listing.price.to_string()),
+                        escape_html(&entry.listing.url),
+                        escape_html(&entry.listing.title),
+                        entry.subscription.id
+                    );
+                }
+                (format!("{header}."), html)
+            })
+            .collect()
+    }
+
+    async fn send_notification(
+        &self,
+        subscription: &Subscription,
+        metadata: &ScrapeMetadata,
+        listing: &Listing,
+        channel_id: ChannelId,
+        silent: bool,
+    ) -> Result<(), String> {
+        let room_id = room_id(&channel_id)?;
+        let plain = format!("New: {} - {} {}", listing.title, listing.price, listing.url);
+        let html = self.format_notification(subscription, metadata, listing);
+        self.send(room_id, &plain, &html, silent).await
+    }
+
+    /// Replies to a command. Replies are sent as preformatted text, since the help message is
+    /// a table.
+    async fn reply(&self, room_id: &str, text: &str) {
+        let html = format!("<pre>{}</pre>", escape_html(text));
+        if let Err(e) = self.send(room_id, text, &html, true).await {
+            tracing::error!("matrix: failed to reply in {room_id}: {e}");
+        }
+    }
+}
+
+fn room_id(channel_id: &ChannelId) -> Result<&str, String> {
+    match channel_id {
+        ChannelId::Matrix { room_id } => Ok(room_id),
+        _ => Err("Invalid channel ID: expected Matrix room.".to_string()),
+    }
+}
+
+/// Extracts the text messages of other users from a sync response.
+fn incoming_messages(sync: &SyncResponse, own_user_id: &str) -> Vec<IncomingMessage> {
+    let mut messages = Vec::new();
+    for (room_id, room) in &sync.rooms.join {
+        for event in &room.timeline.events {
+            if event.kind != "m.room.message" || event.sender == own_user_id {
+                continue;
+            }
+            if event.content.get("msgtype").and_then(|t| t.as_str()) != Some("m.text") {
+                continue;
+            }
+            let Some(body) = event.content.get("body").and_then(|b| b.as_str()) else {
+                continue;
+            };
+
+            let mut hasher = DefaultHasher::new();
+            event.event_id.hash(&mut hasher);
+            messages.push(IncomingMessage {
+                // Event IDs are strings, this only needs to be unique-ish.
+                message_id: hasher.finish(),
+                channel_id: ChannelId::Matrix {
+                    room_id: room_id.clone(),
+                },
+                sender: OwnerId::Matrix {
+                    user_id: event.sender.clone(),
+                },
+                content: body.to_string(),
+            });
+        }
+    }
+    messages
+}
+
+#[async_trait]
+impl Notifier for MatrixIntegration {
+    async fn notify_new_listing(
+        &self,
+        subscription: &Subscription,
+        metadata: &ScrapeMetadata,
+        listing: &Listing,
+        channel_id: ChannelId,
+    ) -> Result<(), String> {
+        self.send_notification(subscription, metadata, listing, channel_id, false)
+            .await
+    }
+
+    async fn notify_new_listing_silently(
+        &self,
+        subscription: &Subscription,
+        metadata: &ScrapeMetadata,
+        listing: &Listing,
+        channel_id: ChannelId,
+    ) -> Result<(), String> {
+        self.send_notification(subscription, metadata, listing, channel_id, true)
+            .await
+    }
+
+    async fn notify_catch_up(
+        &self,
+        held: &[HeldListing],
+        channel_id: ChannelId,
+    ) -> Result<(), String> {
+        let room_id = room_id(&channel_id)?;
+        for (plain, html) in self.format_catch_up(held) {
+            self.send(room_id, &plain, &html, false).await?;
+        }
+        Ok(())
+    }
+
+    async fn send_message(&self, text: &str, channel_id: ChannelId) -> Result<(), String> {
+        self.send(room_id(&channel_id)?, text, &escape_html(text), false)
+            .await
+    }
+
+    async fn send_coconut(&self, channel_id: ChannelId) -> Result<(), String> {
+        self.send_message("🥥", channel_id).await
+    }
+}
+
+#[async_trait]
+impl Controller for MatrixIntegration {
+    fn connection_name(&self) -> Option<&'static str> {
+        Some("matrix")
+    }
+
+    async fn start(self: Box<Self>, context: AppCtx) -> () {
+        let own_user_id = match self.whoami().await {
+            Ok(user_id) => user_id,
+            Err(e) => {
+                tracing::error!("matrix: {e}. The Matrix controller is not started.");
+                context
+                    .status
+                    .set_connection("matrix", ConnectionState::Disconnected, Some(e));
+                return;
+            }
+        };
+        tracing::info!("matrix: logged in as {own_user_id}");
+
+        // Messages sent while the bot was offline are not answered, so the first sync only
+        // fetches where to continue from.
+        let mut since = loop {
+            match self.sync(None, Duration::ZERO).await {
+                Ok(sync) => break sync.next_batch,
+                Err(e) => {
+                    tracing::error!("matrix: {e}");
+                    tokio::time::sleep(SYNC_RETRY_DELAY).await;
+                }
+            }
+        };
+
+        loop {
+            let sync = match self.sync(Some(&since), SYNC_TIMEOUT).await {
+                Ok(sync) => {
+                    context
+                        .status
+                        .set_connection("matrix", ConnectionState::Connected, None);
+                    sync
+                }
+                Err(e) => {
+                    tracing::error!("matrix: {e}");
+                    context
+                        .status
+                        .set_connection("matrix", ConnectionState::Disconnected, Some(e));
+                    tokio::time::sleep(SYNC_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+
+            for room_id in sync.rooms.invite.keys() {
+                tracing::info!("matrix: joining {room_id}");
+                if let Err(e) = self.join(room_id).await {
+                    tracing::error!("matrix: {e}");
+                }
+            }
+
+            for message in incoming_messages(&sync, &own_user_id) {
+                let room_id = match &message.channel_id {
+                    ChannelId::Matrix { room_id } => room_id.clone(),
+                    _ => unreachable!("incoming Matrix messages are always from Matrix rooms"),
+                };
+                match handle_message(message, context.clone()) {
+                    Ok(Some(reply)) | Err(reply) => self.reply(&room_id, &reply).await,
+                    Ok(None) => {}
+                }
+            }
+
+            since = sync.next_batch;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_incoming_messages() {
+        let sync: SyncResponse = serde_json::from_value(json!({
+            "next_batch": "s72595_4483_1934",
+            "rooms": {
+                "join": {
+                    "!abc:example.org": {
+                        "timeline": {
+                            "events": [
+                                {
+                                    "type": "m.room.message",
+                                    "event_id": "$1",
+                                    "sender": "@user:example.org",
+                                    "content": { "msgtype": "m.text", "body": "/list" }
+                                },
+                                {
+                                    "type": "m.room.message",
+                                    "event_id": "$2",
+                                    "sender": "@bot:example.org",
+                                    "content": { "msgtype": "m.notice", "body": "reply" }
+                                },
+                                {
+                                    "type": "m.room.member",
+                                    "event_id": "$3",
+                                    "sender": "@user:example.org",
+                                    "content": { "membership": "join" }
+                                }
+                            ]
+                        }
+                    }
+                },
+                "invite": { "!new:example.org": {} }
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(sync.rooms.invite.len(), 1);
+        let messages = incoming_messages(&sync, "@bot:example.org");
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "/list");
+        assert_eq!(
+            messages[0].channel_id,
+            ChannelId::Matrix {
+                room_id: "!abc:example.org".to_string()
+            }
+        );
+        assert_eq!(
+            messages[0].sender,
+            OwnerId::Matrix {
+                user_id: "@user:example.org".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_catch_up_has_every_listing() {
+        let matrix = MatrixIntegration::new("https://matrix.example.org", String::new()).unwrap();
+        let held: Vec<HeldListing> = (0..70)
+            .map(|id| HeldListing {
+                subscription: Subscription::dummy(),
+                metadata: ScrapeMetadata::default(),
+                listing: Listing {
+                    id,
+                    title: format!("listing {id}"),
+                    ..Listing::dummy()
+                },
+            })
+            .collect();
+
+        let messages = matrix.format_catch_up(&held);
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].0, "70 new listings you missed (1/3).");
+        let html: String = messages.into_iter().map(|(_, html)| html).collect();
+        for id in 0..70 {
+            assert!(html.contains(&format!(">listing {id}<")));
+        }
+    }
+
+    #[test]
+    fn test_homeserver_scheme() {
+        assert!(MatrixIntegration::new("matrix.example.org:8448", String::new()).is_err());
+        assert!(MatrixIntegration::new("mailto:admin@example.org", String::new()).is_err());
+        assert!(MatrixIntegration::new("http://localhost:8008", String::new()).is_ok());
+    }
+
+    #[test]
+    fn test_endpoint() {
+        let matrix = MatrixIntegration::new("https://matrix.example.org/", String::new()).unwrap();
+        assert_eq!(
+            matrix
+                .endpoint(&["rooms", "!abc:example.org", "join"])
+                .as_str(),
+            "https://matrix.example.org/_matrix/client/v3/rooms/!abc:example.org/join"
+        );
+        assert_eq!(
+            matrix.endpoint(&["rooms", "#alias/x:example.org"]).as_str(),
+            "https://matrix.example.org/_matrix/client/v3/rooms/%23alias%2Fx:example.org"
+        );
+    }
+}
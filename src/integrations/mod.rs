@@ -1,18 +1,23 @@
 mod discord;
+mod email;
+mod matrix;
 mod message_handler;
+mod push;
 mod telegram;
 mod terminal;
-use std::sync::Arc;
+mod webhook;
+use std::{sync::Arc, time::Duration};
 
 use crate::{
     AppCtx,
-    models::{ChannelId, Listing, Subscription},
-    parsers::ScrapeMetadata,
+    models::{ChannelId, HeldListing, Listing, ListingDetails, ScrapeMetadata, Subscription},
 };
 
 pub use self::{
-    discord::DiscordController, discord::DiscordNotifier, telegram::TelegramIntegration,
-    terminal::TerminalIntegration,
+    discord::DiscordController, discord::DiscordNotifier, email::EmailNotifier,
+    email::SmtpSettings, email::SmtpTls, matrix::MatrixIntegration, push::PushNotifier,
+    push::PushServer, push::PushSettings, telegram::TelegramIntegration, terminal::TerminalFormat,
+    terminal::TerminalIntegration, webhook::WebhookNotifier,
 };
 
 /// A struct that contains all implemented notifiers.
@@ -25,6 +30,13 @@ pub struct NotifierRegistry {
     pub telegram: Option<Arc<dyn Notifier>>,
     pub discord: Option<Arc<dyn Notifier>>,
     pub terminal: Option<Arc<dyn Notifier>>,
+    pub webhook: Option<Arc<dyn Notifier>>,
+    pub email: Option<Arc<dyn Notifier>>,
+    pub matrix: Option<Arc<dyn Notifier>>,
+    /// ntfy or Gotify, whichever is configured.
+    pub ntfy: Option<Arc<dyn Notifier>>,
+    /// Where operational alerts (e.g. parser breakage) are sent. Alerts are only logged if unset.
+    pub admin_channel: Option<ChannelId>,
 }
 
 impl NotifierRegistry {
@@ -33,8 +45,124 @@ impl NotifierRegistry {
             ChannelId::Telegram { .. } => self.telegram.clone(),
             ChannelId::Discord { .. } => self.discord.clone(),
             ChannelId::Terminal => self.terminal.clone(),
+            ChannelId::Webhook => self.webhook.clone(),
+            ChannelId::Email { .. } => self.email.clone(),
+            ChannelId::Matrix { .. } => self.matrix.clone(),
+            ChannelId::Ntfy { .. } => self.ntfy.clone(),
+            ChannelId::Api => None,
         }
     }
+
+    /// The names of the notifiers that are enabled.
+    pub fn enabled(&self) -> Vec<&'static str> {
+        [
+            ("telegram", &self.telegram),
+            ("discord", &self.discord),
+            ("terminal", &self.terminal),
+            ("webhook", &self.webhook),
+            ("email", &self.email),
+            ("matrix", &self.matrix),
+            ("ntfy", &self.ntfy),
+        ]
+        .into_iter()
+        .filter(|(_, notifier)| notifier.is_some())
+        .map(|(name, _)| name)
+        .collect()
+    }
+
+    /// Sends a plain text message to the admin channel. Failures are logged.
+    pub async fn notify_admin(&self, text: &str) {
+        let Some(channel) = &self.admin_channel else {
+            tracing::warn!("No admin channel configured, alert not sent: {text}");
+            return;
+        };
+        let Some(notifier) = self.notifier_for(channel) else {
+            tracing::warn!("No notifier for admin channel: {channel:?}. Alert not sent: {text}");
+            return;
+        };
+        if let Err(e) = notifier.send_message(text, channel.clone()).await {
+            tracing::error!("Failed to send alert to admin channel: {e}");
+        }
+    }
+}
+
+/// Catch-ups are sent as several messages of at most this many listings, to stay under message
+/// size limits. Discord and Telegram split by length with `split_message` instead.
+pub const MAX_CATCH_UP_LISTINGS: usize = 30;
+
+/// The header of each part of a catch-up that is sent as `parts` messages.
+pub fn catch_up_header(total: usize, part: usize, parts: usize) -> String {
+    match parts {
+        1 => format!("{total} new listings you missed"),
+        _ => format!("{total} new listings you missed ({part}/{parts})"),
+    }
+}
+
+/// Joins the lines into as few messages as possible, each at most `max_len` characters long.
+/// Messages are only split between lines, a line longer than `max_len` gets a message of its own.
+pub fn split_message(lines: impl IntoIterator<Item = String>, max_len: usize) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+
+    for line in lines {
+        let len = line.chars().count();
+        if current_len > 0 && current_len + 1 + len > max_len {
+            messages.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+        if current_len > 0 {
+            current.push('\n');
+            current_len += 1;
+        }
+        current += &line;
+        current_len += len;
+    }
+    if current_len > 0 {
+        messages.push(current);
+    }
+    messages
+}
+
+/// Length of the description excerpt in notifications of enriched listings.
+const DESCRIPTION_EXCERPT_LENGTH: usize = 300;
+
+/// Renders the details of an enriched listing, escaped for Telegram's MarkdownV2. Discord
+/// understands the same escaping.
+pub fn format_details(details: &ListingDetails) -> String {
+    use teloxide::utils::markdown;
+
+    let mut facts = Vec::new();
+    if let Some(condition) = details.condition {
+        facts.push(format!("Condition: {condition}"));
+    }
+    if let Some(warranty) = &details.warranty {
+        facts.push(format!("Warranty: {warranty}"));
+    }
+    if !details.shipping.is_empty() {
+        facts.push(format!("Shipping: {}", details.shipping.join(", ")));
+    }
+
+    let mut lines = Vec::new();
+    if !facts.is_empty() {
+        lines.push(markdown::escape(&facts.join(" | ")));
+    }
+    if !details.description.is_empty() {
+        lines.push(markdown::italic(&markdown::escape(
+            &details.description_excerpt(DESCRIPTION_EXCERPT_LENGTH),
+        )));
+    }
+    lines.join("\n")
+}
+
+/// Escapes text for use in HTML element content and attribute values. Used by the integrations
+/// that send HTML (email, Matrix).
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
 }
 
 // ==== Sending messages ====
@@ -49,6 +177,36 @@ pub trait Notifier: Send + Sync {
         listing: &Listing,
         channel_id: ChannelId,
     ) -> Result<(), String>;
+
+    /// Same as `notify_new_listing`, but the notification should not make a sound. Platforms
+    /// without silent messages send a normal notification.
+    async fn notify_new_listing_silently(
+        &self,
+        subscription: &Subscription,
+        metadata: &ScrapeMetadata,
+        listing: &Listing,
+        channel_id: ChannelId,
+    ) -> Result<(), String> {
+        self.notify_new_listing(subscription, metadata, listing, channel_id)
+            .await
+    }
+
+    /// If set, new listings are held and sent together with `notify_catch_up` this often, instead
+    /// of one by one.
+    fn digest_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Sends every held listing of the channel as a single message.
+    async fn notify_catch_up(
+        &self,
+        held: &[HeldListing],
+        channel_id: ChannelId,
+    ) -> Result<(), String>;
+
+    /// Sends a plain text message that is not about a listing.
+    async fn send_message(&self, text: &str, channel_id: ChannelId) -> Result<(), String>;
+
     async fn send_coconut(&self, channel_id: ChannelId) -> Result<(), String>;
 }
 
@@ -57,6 +215,42 @@ pub trait Notifier: Send + Sync {
 #[async_trait]
 pub trait Controller: Send {
     async fn start(self: Box<Self>, app_context: AppCtx) -> ();
+
+    /// The name the controller reports its connection state under, if it keeps a connection to
+    /// a server. The application is not ready until it reports being connected.
+    fn connection_name(&self) -> Option<&'static str> {
+        None
+    }
 }
 
 // ==== Recieving messages ====
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_message() {
+        let lines = |lengths: &[usize]| -> Vec<String> {
+            lengths.iter().map(|len| "x".repeat(*len)).collect()
+        };
+        let lengths = |messages: Vec<String>| -> Vec<usize> {
+            messages.iter().map(|m| m.chars().count()).collect()
+        };
+
+        assert!(split_message(Vec::new(), 10).is_empty());
+        // Newlines count too: 4 + 1 + 5 fits, another line doesn't.
+        assert_eq!(lengths(split_message(lines(&[4, 5, 3]), 10)), vec![10, 3]);
+        assert_eq!(
+            lengths(split_message(lines(&[2, 20, 2]), 10)),
+            vec![2, 20, 2]
+        );
+        // Characters, not bytes.
+        assert_eq!(split_message(vec!["ő".repeat(10)], 10).len(), 1);
+
+        let many = lines(&[100; 100]);
+        let messages = split_message(many, 2000);
+        assert!(messages.iter().all(|m| m.chars().count() <= 2000));
+        assert_eq!(messages.concat().matches('x').count(), 100 * 100);
+    }
+}
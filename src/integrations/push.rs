@@ -0,0 +1,344 @@
+//! Push notifications to phones through an ntfy or Gotify server.
+//!
+//! ntfy delivers to a topic, which is the ID of `ChannelId::Ntfy`. Gotify has no topics, every
+//! message goes to the application the token belongs to, so the topic is ignored there.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::{Value, json};
+
+use crate::{
+    integrations::{MAX_CATCH_UP_LISTINGS, Notifier, catch_up_header},
+    models::{ChannelId, HeldListing, Listing, ListingType, ScrapeMetadata, Subscription},
+};
+
+/// The push servers that are supported. They take different requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PushServer {
+    Ntfy,
+    Gotify,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PushSettings {
+    pub server: PushServer,
+    /// The base URL of the server, e.g. `https://ntfy.sh`.
+    pub url: String,
+    /// An ntfy access token, or a Gotify application token.
+    pub token: Option<String>,
+}
+
+/// How intrusive a notification is on the phone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Priority {
+    Min,
+    Low,
+    Default,
+    High,
+}
+
+impl Priority {
+    /// Featured listings stand out the same way they do on the site. Bazár listings are mostly
+    /// from shops, those get a quieter notification.
+    fn of(listing: &Listing) -> Self {
+        match listing.listing_type {
+            ListingType::Featured => Priority::High,
+            ListingType::Regular => Priority::Default,
+            ListingType::Bazar => Priority::Low,
+        }
+    }
+
+    /// ntfy priorities go from 1 to 5.
+    fn ntfy(self) -> u8 {
+        match self {
+            Priority::Min => 1,
+            Priority::Low => 2,
+            Priority::Default => 3,
+            Priority::High => 4,
+        }
+    }
+
+    /// Gotify priorities go from 0 to 10. Below 4 the notification is silent, from 8 it pops up.
+    fn gotify(self) -> u8 {
+        match self {
+            Priority::Min => 1,
+            Priority::Low => 3,
+            Priority::Default => 5,
+            Priority::High => 8,
+        }
+    }
+}
+
+/// The contents of a push notification, before they are shaped for the server.
+#[derive(Debug, PartialEq)]
+struct Push<'a> {
+    title: String,
+    message: String,
+    /// Opened when the notification is tapped.
+    click: Option<&'a str>,
+    image: Option<&'a str>,
+    priority: Priority,
+}
+
+pub struct PushNotifier {
+    client: reqwest::Client,
+    settings: PushSettings,
+}
+
+impl PushNotifier {
+    pub fn new(settings: PushSettings) -> Self {
+        const TIMEOUT: Duration = Duration::from_secs(10);
+
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(TIMEOUT)
+                .build()
+                // unsafe for now
+                .unwrap(),
+            settings,
+        }
+    }
+
+    fn listing_push<'a>(&self, subscription: &Subscription, listing: &'a Listing) -> Push<'a> {
+        let mut message = format!("{} - {}", listing.price, listing.cities.join(", "));
+        if !listing.seller_name.is_empty() {
+            message += &format!("\n{} (+{})", listing.seller_name, listing.seller_ratings);
+        }
+        message += &format!(
+            "\nFrom subscription: {} ({})",
+            subscription.name.as_deref().unwrap_or("(unnamed)"),
+            subscription.id
+        );
+
+        Push {
+            title: listing.title.clone(),
+            message,
+            click: Some(&listing.url),
+            image: listing.img_url.as_deref().or_else(|| {
+                listing
+                    .details
+                    .as_ref()
+                    .and_then(|d| d.images.first().map(String::as_str))
+            }),
+            priority: Priority::of(listing),
+        }
+    }
+
+    /// Builds the URL and the JSON body of the request for the configured server.
+    fn request(&self, push: &Push, topic: &str) -> (String, Value) {
+        let base = self.settings.url.trim_end_matches('/');
+        match self.settings.server {
+            // https://docs.ntfy.sh/publish/#publish-as-json
+            PushServer::Ntfy => {
+                let mut body = json!({
+                    "topic": topic,
+                    "title": push.title,
+                    "message": push.message,
+                    "priority": push.priority.ntfy(),
+                });
+                if let Some(click) = push.click {
+                    body["click"] = json!(click);
+                }
+                if let Some(image) = push.image {
+                    body["attach"] = json!(image);
+                }
+                (base.to_string(), body)
+            }
+            // https://gotify.net/docs/msgextras
+            PushServer::Gotify => {
+                let mut notification = json!({});
+                if let Some(click) = push.click {
+                    notification["click"] = json!({ "url": click });
+                }
+                if let Some(image) = push.image {
+                    notification["bigImageUrl"] = json!(image);
+                }
+                let body = json!({
+                    "title": push.title,
+                    "message": push.message,
+                    "priority": push.priority.gotify(),
+                    "extras": { "client::notification": notification },
+                });
+                (format!("{base}/message"), body)
+            }
+        }
+    }
+
+    async fn send(&self, push: Push<'_>, channel_id: ChannelId) -> Result<(), String> {
+        let ChannelId::Ntfy { topic } = channel_id else {
+            return Err("Invalid channel ID: expected ntfy topic.".to_string());
+        };
+        let (url, body) = self.request(&push, &topic);
+
+        let mut request = self.client.post(url).json(&body);
+        if let Some(token) = &self.settings.token {
+            request = match self.settings.server {
+                PushServer::Ntfy => request.bearer_auth(token),
+                PushServer::Gotify => request.header("X-Gotify-Key", token),
+            };
+        }
+        request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map(|_| ())
+            .map_err(|e| format!("Failed to send push notification: {e}"))
+    }
+}
+
+/// Every held listing, as pushes of at most `MAX_CATCH_UP_LISTINGS` listings.
+fn catch_up_pushes(held: &[HeldListing]) -> Vec<Push<'static>> {
+    let parts = held.len().div_ceil(MAX_CATCH_UP_LISTINGS);
+    held.chunks(MAX_CATCH_UP_LISTINGS)
+        .enumerate()
+        .map(|(index, chunk)| Push {
+            title: catch_up_header(held.len(), index + 1, parts),
+            message: chunk
+                .iter()
+                .map(|entry| format!("{} - {}", entry.listing.price, entry.listing.title))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            click: None,
+            image: None,
+            priority: Priority::Default,
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Notifier for PushNotifier {
+    async fn notify_new_listing(
+        &self,
+        subscription: &Subscription,
+        _metadata: &ScrapeMetadata,
+        listing: &Listing,
+        channel_id: ChannelId,
+    ) -> Result<(), String> {
+        self.send(self.listing_push(subscription, listing), channel_id)
+            .await
+    }
+
+    async fn notify_new_listing_silently(
+        &self,
+        subscription: &Subscription,
+        _metadata: &ScrapeMetadata,
+        listing: &Listing,
+        channel_id: ChannelId,
+    ) -> Result<(), String> {
+        let push = Push {
+            priority: Priority::Min,
+            ..self.listing_push(subscription, listing)
+        };
+        self.send(push, channel_id).await
+    }
+
+    async fn notify_catch_up(
+        &self,
+        held: &[HeldListing],
+        channel_id: ChannelId,
+    ) -> Result<(), String> {
+        for push in catch_up_pushes(held) {
+            self.send(push, channel_id.clone()).await?;
+        }
+        Ok(())
+    }
+
+    async fn send_message(&self, text: &str, channel_id: ChannelId) -> Result<(), String> {
+        let push = Push {
+            title: "rozsdhabot".to_string(),
+            message: text.to_string(),
+            click: None,
+            image: None,
+            priority: Priority::Default,
+        };
+        self.send(push, channel_id).await
+    }
+
+    async fn send_coconut(&self, channel_id: ChannelId) -> Result<(), String> {
+        self.send_message("🥥", channel_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notifier(server: PushServer) -> PushNotifier {
+        PushNotifier::new(PushSettings {
+            server,
+            url: "https://push.example.org/".to_string(),
+            token: Some("token".to_string()),
+        })
+    }
+
+    #[test]
+    fn test_catch_up_has_every_listing() {
+        let held: Vec<HeldListing> = (0..70)
+            .map(|id| HeldListing {
+                subscription: Subscription::dummy(),
+                metadata: ScrapeMetadata::default(),
+                listing: Listing {
+                    id,
+                    title: format!("listing {id}"),
+                    ..Listing::dummy()
+                },
+            })
+            .collect();
+
+        let pushes = catch_up_pushes(&held);
+        assert_eq!(pushes.len(), 3);
+        assert_eq!(pushes[2].title, "70 new listings you missed (3/3)");
+        let lines: Vec<&str> = pushes.iter().flat_map(|p| p.message.lines()).collect();
+        assert_eq!(lines.len(), 70);
+        assert_eq!(lines[69], "Free - listing 69");
+    }
+
+    #[test]
+    fn test_requests() {
+        let listing = Listing {
+            img_url: Some("https://example.org/image.jpg".to_string()),
+            listing_type: ListingType::Featured,
+            ..Listing::dummy()
+        };
+
+        let ntfy = notifier(PushServer::Ntfy);
+        let push = ntfy.listing_push(
+            &Subscription {
+                id: 3,
+                ..Subscription::dummy()
+            },
+            &listing,
+        );
+        let (url, body) = ntfy.request(&push, "deals");
+        assert_eq!(url, "https://push.example.org");
+        assert_eq!(body["topic"], "deals");
+        assert_eq!(body["title"], listing.title);
+        assert_eq!(body["click"], listing.url);
+        assert_eq!(body["attach"], "https://example.org/image.jpg");
+        assert_eq!(body["priority"], 4);
+        assert!(body["message"].as_str().unwrap().contains("gpu (3)"));
+
+        let gotify = notifier(PushServer::Gotify);
+        let (url, body) = gotify.request(&push, "deals");
+        assert_eq!(url, "https://push.example.org/message");
+        assert_eq!(body["priority"], 8);
+        let notification = &body["extras"]["client::notification"];
+        assert_eq!(notification["click"]["url"], listing.url);
+        assert_eq!(notification["bigImageUrl"], "https://example.org/image.jpg");
+    }
+
+    #[test]
+    fn test_priority_from_listing_type() {
+        let push = |listing_type| {
+            let listing = Listing {
+                listing_type,
+                ..Listing::dummy()
+            };
+            Priority::of(&listing)
+        };
+        assert_eq!(push(ListingType::Featured), Priority::High);
+        assert_eq!(push(ListingType::Regular), Priority::Default);
+        assert_eq!(push(ListingType::Bazar), Priority::Low);
+    }
+}
@@ -1,11 +1,12 @@
 use crate::{
     AppCtx,
     integrations::{
-        Controller, Notifier,
+        Controller, Notifier, format_details,
         message_handler::{IncomingMessage, handle_message},
+        split_message,
     },
-    models::{ChannelId, Listing, Subscription},
-    parsers::ScrapeMetadata,
+    models::{ChannelId, HeldListing, Listing, ScrapeMetadata, Subscription, format_timestamp},
+    status::ConnectionState,
 };
 
 use async_trait::async_trait;
@@ -17,6 +18,10 @@ use teloxide::{
     types::{InputFile, Message, Update},
 };
 
+/// Telegram rejects longer messages. The limit applies to the text after parsing the markup, so
+/// counting the markup too is on the safe side.
+const MAX_MESSAGE_LENGTH: usize = 4096;
+
 #[derive(Clone)]
 pub struct TelegramIntegration {
     pub bot: teloxide::Bot,
@@ -45,9 +50,33 @@ impl TelegramIntegration {
         let id = sub.id.to_string();
 
         let price = markdown::bold(markdown::escape(listing.price.to_string().as_str()).as_str());
-        let seller_name = markdown::escape(listing.seller_name.as_str());
-        let seller_ratings = markdown::escape(listing.seller_ratings.to_string().as_str());
+        let bumped = match listing.bumped {
+            true => " \\(bumped\\)",
+            false => "",
+        };
+        let business = match listing.seller_is_business {
+            true => " \\(business\\)",
+            false => "",
+        };
+        // Not every site shows the seller in search results.
+        let seller = match (listing.seller_name.is_empty(), listing.seller_is_business) {
+            (true, false) => String::new(),
+            (true, true) => "\\- Business seller".to_string(),
+            (false, _) => format!(
+                "\\- {} \\(\\+{}\\){business}",
+                markdown::escape(listing.seller_name.as_str()),
+                markdown::escape(listing.seller_ratings.to_string().as_str())
+            ),
+        };
         let cities = markdown::escape(listing.cities.join(", ").as_str());
+        let date = match &listing.date {
+            Some(date) => format!(" \\- {}", markdown::escape(&format_timestamp(date))),
+            None => String::new(),
+        };
+        let details = match &listing.details {
+            Some(details) => format_details(details),
+            None => String::new(),
+        };
 
         let pricerange = markdown::escape(
             match (metadata.min_price, metadata.max_price) {
@@ -61,10 +90,11 @@ impl TelegramIntegration {
 
         format!(
             "
-{price} Ft
+{price}{bumped}
 {link}
-{cities}
-\\- {seller_name} \\(\\+{seller_ratings}\\)
+{cities}{date}
+{seller}
+{details}
 
 From subscription:
 {sub_title} \\({id}\\):
@@ -72,16 +102,35 @@ From subscription:
 ",
         )
     }
-}
 
-#[async_trait]
-impl Notifier for TelegramIntegration {
-    async fn notify_new_listing(
+    /// Every held listing is listed, split into as many messages as needed.
+    fn format_catch_up(&self, held: &[HeldListing]) -> Vec<String> {
+        use teloxide::utils::markdown;
+
+        let header = markdown::bold(
+            markdown::escape(format!("{} new listings you missed:", held.len()).as_str()).as_str(),
+        );
+        let lines = held.iter().map(|entry| {
+            let link = markdown::link(
+                &entry.listing.url,
+                markdown::escape(&entry.listing.title).as_str(),
+            );
+            let price = markdown::escape(entry.listing.price.to_string().as_str());
+            format!("{price} \\- {link} \\({}\\)", entry.subscription.id)
+        });
+
+        // An empty line after the header.
+        let lines = [header, String::new()].into_iter().chain(lines);
+        split_message(lines, MAX_MESSAGE_LENGTH)
+    }
+
+    async fn send_notification(
         &self,
         subscription: &Subscription,
         metadata: &ScrapeMetadata,
         listing: &Listing,
         channel_id: ChannelId,
+        silent: bool,
     ) -> Result<(), String> {
         let chat_id = match channel_id {
             ChannelId::Telegram { chat_id } => chat_id,
@@ -102,12 +151,70 @@ impl Notifier for TelegramIntegration {
                 self.format_notification(subscription, metadata, listing),
             )
             .parse_mode(teloxide::types::ParseMode::MarkdownV2)
+            .disable_notification(silent)
             .await
         {
             Ok(_) => Ok(()),
             Err(e) => Err(format!("Failed to send message: {e}")),
         }
     }
+}
+
+#[async_trait]
+impl Notifier for TelegramIntegration {
+    async fn notify_new_listing(
+        &self,
+        subscription: &Subscription,
+        metadata: &ScrapeMetadata,
+        listing: &Listing,
+        channel_id: ChannelId,
+    ) -> Result<(), String> {
+        self.send_notification(subscription, metadata, listing, channel_id, false)
+            .await
+    }
+
+    async fn notify_new_listing_silently(
+        &self,
+        subscription: &Subscription,
+        metadata: &ScrapeMetadata,
+        listing: &Listing,
+        channel_id: ChannelId,
+    ) -> Result<(), String> {
+        self.send_notification(subscription, metadata, listing, channel_id, true)
+            .await
+    }
+
+    async fn notify_catch_up(
+        &self,
+        held: &[HeldListing],
+        channel_id: ChannelId,
+    ) -> Result<(), String> {
+        let chat_id = match channel_id {
+            ChannelId::Telegram { chat_id } => chat_id,
+            _ => return Err("Invalid channel ID: expected Telegram channel.".to_string()),
+        };
+
+        for message in self.format_catch_up(held) {
+            self.bot
+                .send_message(chat_id, message)
+                .parse_mode(teloxide::types::ParseMode::MarkdownV2)
+                .await
+                .map_err(|e| format!("Failed to send message: {e}"))?;
+        }
+        Ok(())
+    }
+
+    async fn send_message(&self, text: &str, channel_id: ChannelId) -> Result<(), String> {
+        let chat_id = match channel_id {
+            ChannelId::Telegram { chat_id } => chat_id,
+            _ => return Err("Invalid channel ID: expected Telegram channel.".to_string()),
+        };
+
+        match self.bot.send_message(chat_id, text).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Failed to send message: {e}")),
+        }
+    }
 
     async fn send_coconut(&self, channel_id: ChannelId) -> Result<(), String> {
         let chat_id = match channel_id {
@@ -128,8 +235,16 @@ impl Notifier for TelegramIntegration {
 
 #[async_trait]
 impl Controller for TelegramIntegration {
+    fn connection_name(&self) -> Option<&'static str> {
+        Some("telegram")
+    }
+
     /// Modifies the AppCtx by adding itself as a controller.
     async fn start(self: Box<Self>, context: AppCtx) -> () {
+        // Long polling doesn't tell if the bot can reach Telegram, so it's checked separately.
+        let watcher = tokio::spawn(watch_connection(self.bot.clone(), context.clone()));
+
+        let status = context.status.clone();
         let handler = Update::filter_message().endpoint(move |bot: Bot, msg: Message| {
             let context = context.clone();
             async { telegram_handler(bot, msg, context).await }
@@ -139,6 +254,33 @@ impl Controller for TelegramIntegration {
             .build()
             .dispatch()
             .await;
+
+        watcher.abort();
+        status.set_connection(
+            "telegram",
+            ConnectionState::Disconnected,
+            Some("dispatcher stopped".to_string()),
+        );
+    }
+}
+
+/// Periodically checks that the bot can log in to Telegram.
+async fn watch_connection(bot: Bot, context: AppCtx) {
+    const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        match bot.get_me().await {
+            Ok(_) => context
+                .status
+                .set_connection("telegram", ConnectionState::Connected, None),
+            Err(e) => context.status.set_connection(
+                "telegram",
+                ConnectionState::Disconnected,
+                Some(e.to_string()),
+            ),
+        }
     }
 }
 
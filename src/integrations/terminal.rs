@@ -1,18 +1,137 @@
 use async_trait::async_trait;
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, BufReader};
 
 use crate::{
-    integrations::Notifier,
-    models::{ChannelId, Listing, Subscription},
-    parsers::ScrapeMetadata,
+    AppCtx,
+    integrations::{
+        Controller, DESCRIPTION_EXCERPT_LENGTH, Notifier,
+        message_handler::{IncomingMessage, handle_message},
+    },
+    models::{
+        ChannelId, HeldListing, Listing, OwnerId, ScrapeMetadata, Subscription, format_timestamp,
+    },
 };
 
-/// A notifier that prints the listing to the terminal. Intended for testing, but it's
-/// fully functional.
-pub struct TerminalIntegration;
+/// How the terminal integration prints what it sends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum TerminalFormat {
+    /// A line per listing: the title and the URL.
+    #[default]
+    Human,
+    /// Every detail of the listing on multiple lines.
+    Rich,
+    /// A JSON object per line, for piping into `jq` and other scripts. Logs go to stderr, so
+    /// stdout only contains these lines.
+    Json,
+}
+
+/// A line of the JSON output. The `event` field tells them apart.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum JsonLine<'a> {
+    NewListing {
+        subscription_id: u64,
+        subscription_name: Option<&'a str>,
+        #[serde(flatten)]
+        listing: &'a Listing,
+    },
+    Message {
+        text: &'a str,
+    },
+}
+
+/// Prints the listings to the terminal, and reads commands from stdin. Intended for testing and
+/// single-user setups, but it's fully functional.
+#[derive(Clone)]
+pub struct TerminalIntegration {
+    format: TerminalFormat,
+}
+
 impl TerminalIntegration {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(format: TerminalFormat) -> Self {
+        Self { format }
+    }
+
+    fn format_listing(&self, subscription: &Subscription, listing: &Listing) -> String {
+        match self.format {
+            TerminalFormat::Human => format!("New listing: {} {}", listing.title, listing.url),
+            TerminalFormat::Rich => rich_listing(subscription, listing),
+            TerminalFormat::Json => json_line(&JsonLine::NewListing {
+                subscription_id: subscription.id,
+                subscription_name: subscription.name.as_deref(),
+                listing,
+            }),
+        }
+    }
+
+    fn format_message(&self, text: &str) -> String {
+        match self.format {
+            TerminalFormat::Human | TerminalFormat::Rich => text.to_string(),
+            TerminalFormat::Json => json_line(&JsonLine::Message { text }),
+        }
+    }
+}
+
+fn json_line(line: &JsonLine) -> String {
+    // Serializing listings can't fail, they have no maps with non-string keys.
+    serde_json::to_string(line).unwrap()
+}
+
+fn rich_listing(subscription: &Subscription, listing: &Listing) -> String {
+    let mut lines = vec![
+        format!("=== {} ===", listing.title),
+        format!("Price:  {}", listing.price),
+        format!("URL:    {}", listing.url),
+    ];
+    if !listing.cities.is_empty() {
+        lines.push(format!("Cities: {}", listing.cities.join(", ")));
+    }
+    if let Some(date) = &listing.date {
+        lines.push(format!("Date:   {}", format_timestamp(date)));
+    }
+    let mut flags = Vec::new();
+    if listing.bumped {
+        flags.push("bumped");
     }
+    if listing.frozen {
+        flags.push("frozen");
+    }
+    if listing.seller_is_business {
+        flags.push("business seller");
+    }
+    lines.push(format!(
+        "Seller: {} (+{}){}",
+        listing.seller_name,
+        listing.seller_ratings,
+        match flags.is_empty() {
+            true => String::new(),
+            false => format!(" [{}]", flags.join(", ")),
+        }
+    ));
+    if let Some(image) = &listing.img_url {
+        lines.push(format!("Image:  {image}"));
+    }
+    if let Some(details) = &listing.details {
+        if let Some(condition) = details.condition {
+            lines.push(format!("Condition: {condition}"));
+        }
+        if let Some(warranty) = &details.warranty {
+            lines.push(format!("Warranty:  {warranty}"));
+        }
+        if !details.shipping.is_empty() {
+            lines.push(format!("Shipping:  {}", details.shipping.join(", ")));
+        }
+        if !details.description.is_empty() {
+            lines.push(details.description_excerpt(DESCRIPTION_EXCERPT_LENGTH));
+        }
+    }
+    lines.push(format!(
+        "From subscription: {} ({})",
+        subscription.name.as_deref().unwrap_or("(unnamed)"),
+        subscription.id
+    ));
+    lines.join("\n")
 }
 
 #[async_trait]
@@ -20,17 +139,132 @@ impl Notifier for TerminalIntegration {
     async fn notify_new_listing(
         &self,
         subscription: &Subscription,
-        metadata: &ScrapeMetadata,
+        _metadata: &ScrapeMetadata,
         listing: &Listing,
-        channel_id: ChannelId,
+        _channel_id: ChannelId,
     ) -> Result<(), String> {
-        println!("New listing: {} {}", listing.title, listing.url);
-        // println!("{listing:?}");
+        println!("{}", self.format_listing(subscription, listing));
         // Can't really fail
         Ok(())
     }
 
-    async fn send_coconut(&self, channel_id: ChannelId) -> Result<(), String> {
+    async fn notify_catch_up(
+        &self,
+        held: &[HeldListing],
+        _channel_id: ChannelId,
+    ) -> Result<(), String> {
+        match self.format {
+            TerminalFormat::Human => {
+                println!("{} new listings you missed:", held.len());
+                for entry in held {
+                    println!("  {} {}", entry.listing.title, entry.listing.url);
+                }
+            }
+            // Held listings look the same as the rest, so scripts don't need to care.
+            TerminalFormat::Rich | TerminalFormat::Json => {
+                for entry in held {
+                    println!(
+                        "{}",
+                        self.format_listing(&entry.subscription, &entry.listing)
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn send_message(&self, text: &str, _channel_id: ChannelId) -> Result<(), String> {
+        println!("{}", self.format_message(text));
         Ok(())
     }
+
+    async fn send_coconut(&self, _channel_id: ChannelId) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Controller for TerminalIntegration {
+    async fn start(self: Box<Self>, context: AppCtx) {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        let mut message_id = 0;
+
+        loop {
+            let line = match lines.next_line().await {
+                Ok(Some(line)) => line,
+                // Detached from a terminal (e.g. in a container), notifications still work.
+                Ok(None) => {
+                    tracing::info!("terminal: stdin closed, no longer reading commands");
+                    return;
+                }
+                Err(e) => {
+                    tracing::error!("terminal: failed to read stdin: {e}");
+                    return;
+                }
+            };
+
+            message_id += 1;
+            let message = IncomingMessage {
+                message_id,
+                channel_id: ChannelId::Terminal,
+                sender: OwnerId::Terminal,
+                content: line,
+            };
+            match handle_message(message, context.clone()) {
+                Ok(Some(reply)) | Err(reply) => println!("{}", self.format_message(&reply)),
+                Ok(None) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ListingDetails;
+
+    #[test]
+    fn test_formats() {
+        let subscription = Subscription {
+            id: 7,
+            ..Subscription::dummy()
+        };
+        let listing = Listing {
+            title: "RTX 3080".to_string(),
+            cities: vec!["Budapest".to_string()],
+            details: Some(ListingDetails {
+                description: "Barely used".to_string(),
+                ..Default::default()
+            }),
+            ..Listing::dummy()
+        };
+
+        let human = TerminalIntegration::new(TerminalFormat::Human);
+        assert_eq!(
+            human.format_listing(&subscription, &listing),
+            format!("New listing: RTX 3080 {}", listing.url)
+        );
+
+        let rich =
+            TerminalIntegration::new(TerminalFormat::Rich).format_listing(&subscription, &listing);
+        assert!(rich.contains("Cities: Budapest"));
+        assert!(rich.contains("Barely used"));
+        assert!(rich.ends_with("From subscription: gpu (7)"));
+
+        let json = TerminalIntegration::new(TerminalFormat::Json);
+        let line = json.format_listing(&subscription, &listing);
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["event"], "new_listing");
+        assert_eq!(value["subscription_id"], 7);
+        assert_eq!(value["subscription_name"], "gpu");
+        assert_eq!(value["title"], "RTX 3080");
+        assert_eq!(value["url"], listing.url);
+        assert_eq!(value["details"]["description"], "Barely used");
+
+        let value: serde_json::Value =
+            serde_json::from_str(&json.format_message("/help\nline two")).unwrap();
+        assert_eq!(value["event"], "message");
+        assert_eq!(value["text"], "/help\nline two");
+    }
 }
@@ -0,0 +1,278 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::{
+    integrations::Notifier,
+    models::{ChannelId, HeldListing, Listing, ScrapeMetadata, Subscription},
+};
+
+/// Requests are signed with this header if a secret is configured. The value is
+/// `sha256=<hex encoded HMAC-SHA256 of the body>`.
+pub const SIGNATURE_HEADER: &str = "X-Rozsdhabot-Signature";
+
+/// A failed request is tried this many times in total.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Sends notifications as JSON POST requests to a configured URL, for piping listings into other
+/// tools.
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+    secret: Option<String>,
+    /// Doubled after every failed attempt.
+    retry_delay: Duration,
+}
+
+/// The body of every request. The `event` field tells them apart.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Payload<'a> {
+    NewListing {
+        subscription: &'a Subscription,
+        metadata: &'a ScrapeMetadata,
+        listing: &'a Listing,
+    },
+    CatchUp {
+        listings: &'a [HeldListing],
+    },
+    Message {
+        text: &'a str,
+    },
+}
+
+impl WebhookNotifier {
+    pub fn new(url: String, secret: Option<String>) -> Self {
+        const TIMEOUT: Duration = Duration::from_secs(10);
+
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(TIMEOUT)
+                .build()
+                // unsafe for now
+                .unwrap(),
+            url,
+            secret,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+
+    fn signature(&self, body: &[u8]) -> Option<String> {
+        let secret = self.secret.as_ref()?;
+        // HMAC accepts keys of any length.
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        Some(format!(
+            "sha256={}",
+            hex::encode(mac.finalize().into_bytes())
+        ))
+    }
+
+    /// Posts the payload, retrying on network errors and server errors. Client errors (4xx other
+    /// than 429) are not retried, those won't go away by themselves.
+    async fn post(&self, payload: &Payload<'_>) -> Result<(), String> {
+        let body = serde_json::to_vec(payload).map_err(|e| e.to_string())?;
+        let signature = self.signature(&body);
+
+        let mut delay = self.retry_delay;
+        for attempt in 1..=MAX_ATTEMPTS {
+            let mut request = self
+                .client
+                .post(&self.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+
+            let error = match request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response)
+                    if response.status().is_client_error()
+                        && response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS =>
+                {
+                    return Err(format!(
+                        "Webhook rejected the request: {}",
+                        response.status()
+                    ));
+                }
+                Ok(response) => format!("Webhook responded with {}", response.status()),
+                Err(e) => format!("Failed to send webhook request: {e}"),
+            };
+
+            if attempt == MAX_ATTEMPTS {
+                return Err(error);
+            }
+            tracing::warn!("{error}. Retrying in {}ms", delay.as_millis());
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+
+        unreachable!("the last attempt always returns")
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify_new_listing(
+        &self,
+        subscription: &Subscription,
+        metadata: &ScrapeMetadata,
+        listing: &Listing,
+        _channel_id: ChannelId,
+    ) -> Result<(), String> {
+        self.post(&Payload::NewListing {
+            subscription,
+            metadata,
+            listing,
+        })
+        .await
+    }
+
+    async fn notify_catch_up(
+        &self,
+        held: &[HeldListing],
+        _channel_id: ChannelId,
+    ) -> Result<(), String> {
+        self.post(&Payload::CatchUp { listings: held }).await
+    }
+
+    async fn send_message(&self, text: &str, _channel_id: ChannelId) -> Result<(), String> {
+        self.post(&Payload::Message { text }).await
+    }
+
+    /// There's no one to appreciate it.
+    async fn send_coconut(&self, _channel_id: ChannelId) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// A received request: lowercase headers and the body.
+    type Received = (Vec<(String, String)>, String);
+
+    /// A minimal HTTP server that answers with the given statuses in order, and records the
+    /// requests.
+    async fn stub_server(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<Received>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let requests = received.clone();
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = Vec::new();
+                let mut chunk = [0; 4096];
+                let (head, body) = loop {
+                    let read = stream.read(&mut chunk).await.unwrap();
+                    buffer.extend_from_slice(&chunk[..read]);
+                    let text = String::from_utf8_lossy(&buffer).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|l| {
+                                l.to_lowercase()
+                                    .strip_prefix("content-length:")
+                                    .map(|v| v.trim().parse::<usize>().unwrap())
+                            })
+                            .unwrap_or(0);
+                        if body.len() >= length {
+                            break (head.to_string(), body.to_string());
+                        }
+                    }
+                };
+                let headers = head
+                    .lines()
+                    .skip(1)
+                    .filter_map(|l| l.split_once(':'))
+                    .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+                    .collect();
+                requests.lock().unwrap().push((headers, body));
+
+                let response = format!(
+                    "HTTP/1.1 {status} Stub\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, received)
+    }
+
+    fn metadata() -> ScrapeMetadata {
+        ScrapeMetadata {
+            name: None,
+            category: Some("Videókártya".to_string()),
+            min_price: None,
+            max_price: None,
+        }
+    }
+
+    fn notifier(url: String) -> WebhookNotifier {
+        WebhookNotifier {
+            retry_delay: Duration::from_millis(10),
+            ..WebhookNotifier::new(url, Some("secret".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_signed_payload_with_retry() {
+        let (url, received) = stub_server(vec![500, 200]).await;
+        let notifier = notifier(url);
+
+        notifier
+            .notify_new_listing(
+                &Subscription {
+                    id: 7,
+                    ..Subscription::dummy()
+                },
+                &metadata(),
+                &Listing::dummy(),
+                ChannelId::Webhook,
+            )
+            .await
+            .unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2, "the failed request should be retried");
+
+        let (headers, body) = &received[1];
+        let json: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(json["event"], "new_listing");
+        assert_eq!(json["subscription"]["id"], 7);
+        assert_eq!(json["metadata"]["category"], "Videókártya");
+        assert_eq!(json["listing"]["id"], Listing::dummy().id);
+
+        let signature = headers
+            .iter()
+            .find(|(k, _)| k == &SIGNATURE_HEADER.to_lowercase())
+            .map(|(_, v)| v.clone());
+        assert_eq!(signature, notifier.signature(body.as_bytes()));
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let (url, received) = stub_server(vec![404, 200]).await;
+
+        let result = notifier(url)
+            .send_message("hello", ChannelId::Webhook)
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+}
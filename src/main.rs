@@ -6,22 +6,30 @@ use std::{
 use crate::{
     config::AppConfig,
     integrations::NotifierRegistry,
-    monitor::MonitorManager,
+    models::Subscription,
+    monitor::{MonitorManager, run_catch_up},
+    status::{AppStatus, ConnectionState},
     storage::{
-        DummyPersistence, FilePersistence, Persistence, RuntimeStateStore, SubscriptionStore,
+        ChannelStore, DummyPersistence, FilePersistence, Persistence, RuntimeStateStore,
+        SubscriptionStore,
     },
+    web::HttpSettings,
 };
 use tokio::time::sleep;
 use tracing_subscriber::{EnvFilter, fmt};
 
 mod config;
 mod fetcher;
+mod health;
+mod metrics;
 mod models;
 mod monitor;
+mod status;
 
 mod integrations;
 mod parsers;
 mod storage;
+mod web;
 
 // TODO: kód összetartó kókusz
 // TODO: a kókuszt ÉJÁJJÁL ellenőrzi
@@ -38,32 +46,50 @@ async fn main() -> anyhow::Result<()> {
         Err(_) => panic!("Nincs kokusz, nincs program."),
     }
 
-    // Load the environment variables
-    // this can crash if config is invalid
-    let config = AppConfig::get_config().expect("Unable to load configuration");
-    let (controllers, notifiers) = AppConfig::get_integrations(&config).await;
+    // Load the config file and the environment variables
+    let config =
+        AppConfig::get_config().map_err(|e| anyhow::anyhow!("Invalid configuration: {e}"))?;
+    let (controllers, notifiers) = AppConfig::get_integrations(&config)
+        .await
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {e}"))?;
+    models::set_display_timezone(config.display_timezone);
+    models::set_default_interval(config.default_interval);
 
-    const DATA_DIR: &str = "data";
     // Deciding what kind of persistence to use.
     let saver: Arc<dyn Persistence> = if config.disable_saving {
         tracing::warn!(
-            "Nothing is saved to or loaded from disk. Disable in .env file if this is not intended."
+            "Nothing is saved to or loaded from disk. Set DISABLE_SAVING to false if this is not intended."
         );
         Arc::new(DummyPersistence)
     } else {
-        Arc::new(
-            FilePersistence::new(DATA_DIR).expect("Failed to to initalize file based persistence"),
-        )
+        // Pages that break the parsers are saved next to the data for debugging.
+        health::PARSER_HEALTH
+            .lock()
+            .unwrap()
+            .set_dump_dir(config.data_dir.join("parser_failures"));
+        Arc::new(FilePersistence::new(&config.data_dir).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to initialize file based persistence in {}: {e}",
+                config.data_dir.display()
+            )
+        })?)
     };
 
+    metrics::METRICS
+        .lock()
+        .unwrap()
+        .set_fetch_timeout(config.monitor.fetch_timeout);
+
     let app_context = AppCtx {
         notifiers,
+        public_url: config.http.as_ref().map(|http| http.public_url.clone()),
+        monitor_manager: Arc::new(Mutex::new(MonitorManager::new(config.monitor))),
         ..AppCtx::with_persistence(saver)
     };
 
     // Each controller is launched in a separate task on startup.
     // let controllers: Vec<Arc<dyn Controller>>;
-    run_app(app_context, controllers).await;
+    run_app(app_context, controllers, config.http, config.stagger).await;
     Ok(())
 }
 
@@ -77,27 +103,81 @@ pub struct AppCtx {
     pub subscription_store: Arc<Mutex<SubscriptionStore>>,
     /// Stores the runtime state of monitors. This is modified by the monitors themselves.
     pub runtime_store: Arc<Mutex<RuntimeStateStore>>,
+    /// Stores channel wide settings (e.g. quiet hours). Modified by the controllers, read by the
+    /// monitors.
+    pub channel_store: Arc<Mutex<ChannelStore>>,
     /// Manages the monitors, state is modified by the controllers.
     pub monitor_manager: Arc<Mutex<MonitorManager>>,
     /// Passed along to each monitor for them to use. Immutable after startup.
     pub notifiers: NotifierRegistry,
+    /// Where the HTTP server can be reached, if it's enabled. Used for the links to feeds.
+    pub public_url: Option<String>,
+    /// Startup progress and the connection state of the integrations, for the health checks.
+    pub status: Arc<AppStatus>,
+    /// The same one the stores use. Only kept here to be described by `/status`.
+    pub persistence: Arc<dyn Persistence>,
 }
 
 use integrations::Controller;
 
 impl AppCtx {
+    /// Restarts the monitor of the subscription, which also stops it if the subscription is
+    /// paused. Called after every change to a subscription.
+    pub fn restart_monitor(&self, subscription: Subscription) {
+        self.monitor_manager.lock().unwrap().restart_monitor(
+            subscription,
+            self.runtime_store.clone(),
+            self.channel_store.clone(),
+            self.notifiers.clone(),
+        );
+    }
+
+    /// Applies `update` to the subscription and restarts its monitor. Returns `None` if the
+    /// subscription doesn't exist.
+    pub fn update_subscription(
+        &self,
+        id: u64,
+        update: impl FnOnce(&mut Subscription),
+    ) -> Option<Subscription> {
+        let subscription = self
+            .subscription_store
+            .lock()
+            .unwrap()
+            .update_subscription(id, update)?;
+        self.restart_monitor(subscription.clone());
+        Some(subscription)
+    }
+
+    /// Stops the monitor of the subscription, and removes it along with its state. Returns false
+    /// if it didn't exist.
+    pub fn delete_subscription(&self, id: u64) -> bool {
+        self.monitor_manager.lock().unwrap().stop_monitor(id);
+        self.runtime_store.lock().unwrap().remove(id);
+        metrics::METRICS.lock().unwrap().remove_subscription(id);
+        self.subscription_store
+            .lock()
+            .unwrap()
+            .remove_subscription(id)
+    }
+
     /// Create a new context by fully specifying all fields.
     fn new(
         subscription_store: Arc<Mutex<SubscriptionStore>>,
         runtime_store: Arc<Mutex<RuntimeStateStore>>,
+        channel_store: Arc<Mutex<ChannelStore>>,
         monitor_manager: Arc<Mutex<MonitorManager>>,
         notifiers: NotifierRegistry,
+        persistence: Arc<dyn Persistence>,
     ) -> Self {
         Self {
             subscription_store,
             runtime_store,
+            channel_store,
             monitor_manager,
             notifiers,
+            public_url: None,
+            status: Arc::new(AppStatus::default()),
+            persistence,
         }
     }
 
@@ -110,8 +190,10 @@ impl AppCtx {
             Arc::new(Mutex::new(
                 RuntimeStateStore::new(persistence.clone()).unwrap(),
             )),
+            Arc::new(Mutex::new(ChannelStore::new(persistence.clone()).unwrap())),
             Arc::new(Mutex::new(MonitorManager::default())),
             NotifierRegistry::default(),
+            persistence,
         )
     }
 }
@@ -126,26 +208,56 @@ impl Default for AppCtx {
             Arc::new(Mutex::new(
                 RuntimeStateStore::new(Arc::new(DummyPersistence {})).unwrap(),
             )),
+            Arc::new(Mutex::new(
+                ChannelStore::new(Arc::new(DummyPersistence {})).unwrap(),
+            )),
             Arc::new(Mutex::new(MonitorManager::default())),
             NotifierRegistry::default(),
+            Arc::new(DummyPersistence {}),
         )
     }
 }
 
 fn init_tracing() {
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    fmt().with_env_filter(filter).with_target(false).init();
+    // stdout is left to the terminal integration, e.g. for its JSON lines.
+    fmt()
+        .with_env_filter(filter)
+        .with_target(false)
+        .with_writer(std::io::stderr)
+        .init();
     tracing::info!("🦀🦀🦀 Logging initialized, welcome to rozsdhabot! 🦀🦀🦀");
 }
 
-async fn run_app(context: AppCtx, controllers: Controllers) {
+// Holding the subscription store lock across the stagger is intentional, see below.
+#[allow(clippy::await_holding_lock)]
+async fn run_app(
+    context: AppCtx,
+    controllers: Controllers,
+    http: Option<HttpSettings>,
+    stagger: Duration,
+) {
     // let mut handles = Vec::new();
 
+    // Started first so the health checks answer during startup. Everything else answers 503
+    // until the monitors are started, since the subscription store is locked until then.
+    if let Some(http) = http {
+        tokio::spawn(web::serve(http, context.clone()));
+    }
+
+    // Not ready until the integrations have connected.
+    for controller in &controllers {
+        if let Some(name) = controller.connection_name() {
+            context
+                .status
+                .set_connection(name, ConnectionState::Connecting, None);
+        }
+    }
+
     // Start monitors that were loaded from disk.
-    const STAGGER: Duration = Duration::from_millis(1250);
     tracing::info!(
         "Starting all saved subscriptions with {}ms stagger. This may take a while...",
-        STAGGER.as_millis()
+        stagger.as_millis()
     );
 
     // Persistently holding the mutex guard is fine as long as the handlers are launched
@@ -165,13 +277,21 @@ async fn run_app(context: AppCtx, controllers: Controllers) {
         context.monitor_manager.lock().unwrap().start_monitor(
             sub,
             context.runtime_store.clone(),
+            context.channel_store.clone(),
             context.notifiers.clone(),
         );
         // Staggared startup to avoid rate limiting
         // The mutex is held for the duration of startup.
-        sleep(STAGGER).await;
+        sleep(stagger).await;
     }
 
+    // Sends the listings held back during quiet hours.
+    tokio::spawn(run_catch_up(
+        context.channel_store.clone(),
+        context.runtime_store.clone(),
+        context.notifiers.clone(),
+    ));
+
     // Don't move this before the monitor launch because of a deadlock with the discord controller.
     for controller in controllers {
         let context = context.clone();
@@ -179,7 +299,9 @@ async fn run_app(context: AppCtx, controllers: Controllers) {
             controller.start(context).await;
         });
     }
+    context.status.set_ready();
+    tracing::info!("Every saved subscription is started");
 
     // SIGINT I think.
-    tokio::signal::ctrl_c().await;
+    let _ = tokio::signal::ctrl_c().await;
 }
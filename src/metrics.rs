@@ -0,0 +1,348 @@
+//! Counters and histograms exported at `/metrics` in the Prometheus text format, for alerting
+//! when scraping or notifying degrades.
+//!
+//! Gauges that can be read from the application state (e.g. running monitors) are not kept here,
+//! those are passed in when rendering.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::parsers::{Field, ParseFailureKind, ParsedPage};
+
+/// Shared by every monitor, like the parser health.
+pub static METRICS: LazyLock<Mutex<Metrics>> = LazyLock::new(|| Mutex::new(Metrics::default()));
+
+/// Fetches are rate limited. The top bucket is the fetch timeout, 15 seconds by default, see
+/// [`Metrics::set_fetch_timeout`].
+const FETCH_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 15.0];
+
+/// The data files are small, writes should take milliseconds.
+const WRITE_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0];
+
+struct Histogram {
+    buckets: Vec<f64>,
+    /// Not cumulative, the count of each bucket alone. The last one is `+Inf`.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(buckets: &[f64]) -> Self {
+        Self {
+            buckets: buckets.to_vec(),
+            counts: vec![0; buckets.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = self
+            .buckets
+            .iter()
+            .position(|le| seconds <= *le)
+            .unwrap_or(self.buckets.len());
+        self.counts[bucket] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    /// `labels` are added before `le`, e.g. `file="state",`.
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (le, count) in self.buckets.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{name}_bucket{{{labels}le=\"{le}\"}} {cumulative}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{{labels}le=\"+Inf\"}} {}", self.count);
+        let labels = match labels.trim_end_matches(',') {
+            "" => String::new(),
+            labels => format!("{{{labels}}}"),
+        };
+        let _ = writeln!(out, "{name}_sum{labels} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{labels} {}", self.count);
+    }
+}
+
+pub struct Metrics {
+    /// By subscription ID and whether the scrape succeeded.
+    scrapes: BTreeMap<(u64, bool), u64>,
+    fetch_duration: Histogram,
+    /// By HTTP status code. Requests that got no response at all are counted as `error`.
+    fetch_responses: BTreeMap<String, u64>,
+    /// By parser.
+    parsed_listings: BTreeMap<&'static str, u64>,
+    /// By parser, field and failure kind.
+    parse_failures: BTreeMap<(&'static str, String, String), u64>,
+    /// By integration and whether sending succeeded.
+    notifications: BTreeMap<(&'static str, bool), u64>,
+    /// By data file.
+    persistence_writes: BTreeMap<String, Histogram>,
+    last_persistence_write: Option<DateTime<Utc>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            scrapes: BTreeMap::new(),
+            fetch_duration: Histogram::new(FETCH_BUCKETS),
+            fetch_responses: BTreeMap::new(),
+            parsed_listings: BTreeMap::new(),
+            parse_failures: BTreeMap::new(),
+            notifications: BTreeMap::new(),
+            persistence_writes: BTreeMap::new(),
+            last_persistence_write: None,
+        }
+    }
+}
+
+impl Metrics {
+    /// Replaces the fetch duration buckets above the timeout with the timeout itself, since no
+    /// fetch takes longer. Meant to be called on startup, the recorded fetches are dropped.
+    pub fn set_fetch_timeout(&mut self, timeout: Duration) {
+        let timeout = timeout.as_secs_f64();
+        let mut buckets: Vec<f64> = FETCH_BUCKETS
+            .iter()
+            .copied()
+            .filter(|le| *le < timeout)
+            .collect();
+        buckets.push(timeout);
+        self.fetch_duration = Histogram::new(&buckets);
+    }
+
+    pub fn record_scrape(&mut self, subscription_id: u64, success: bool) {
+        *self.scrapes.entry((subscription_id, success)).or_default() += 1;
+    }
+
+    /// Drops the scrape counts of a deleted subscription, so it's no longer exported.
+    pub fn remove_subscription(&mut self, subscription_id: u64) {
+        self.scrapes.retain(|(id, _), _| *id != subscription_id);
+    }
+
+    /// `status` is `None` if no response was received.
+    pub fn record_fetch(&mut self, duration: Duration, status: Option<u16>) {
+        self.fetch_duration.observe(duration);
+        let status = status.map_or("error".to_string(), |s| s.to_string());
+        *self.fetch_responses.entry(status).or_default() += 1;
+    }
+
+    pub fn record_page(&mut self, parser: &'static str, page: &ParsedPage) {
+        *self.parsed_listings.entry(parser).or_default() += page.listings.len() as u64;
+        for failure in &page.failures {
+            self.record_parse_failure(parser, failure.field, failure.kind);
+        }
+    }
+
+    pub fn record_parse_failure(
+        &mut self,
+        parser: &'static str,
+        field: Field,
+        kind: ParseFailureKind,
+    ) {
+        let key = (parser, format!("{field:?}"), format!("{kind:?}"));
+        *self.parse_failures.entry(key).or_default() += 1;
+    }
+
+    pub fn record_notification(&mut self, integration: &'static str, success: bool) {
+        *self
+            .notifications
+            .entry((integration, success))
+            .or_default() += 1;
+    }
+
+    pub fn record_persistence_write(&mut self, file: &str, duration: Duration) {
+        self.persistence_writes
+            .entry(file.to_string())
+            .or_insert_with(|| Histogram::new(WRITE_BUCKETS))
+            .observe(duration);
+        self.last_persistence_write = Some(Utc::now());
+    }
+
+    /// When a data file was last saved, for `/status`.
+    pub fn last_persistence_write(&self) -> Option<DateTime<Utc>> {
+        self.last_persistence_write
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self, active_monitors: usize) -> String {
+        let mut out = String::new();
+        let result = |success: bool| match success {
+            true => "success",
+            false => "failure",
+        };
+
+        header(
+            &mut out,
+            "scrapes_total",
+            "counter",
+            "Scrapes per subscription.",
+        );
+        for ((id, success), count) in &self.scrapes {
+            let _ = writeln!(
+                out,
+                "rozsdhabot_scrapes_total{{subscription=\"{id}\",result=\"{}\"}} {count}",
+                result(*success)
+            );
+        }
+
+        header(
+            &mut out,
+            "fetch_duration_seconds",
+            "histogram",
+            "Time from sending a request to receiving the whole page, without rate limiting.",
+        );
+        self.fetch_duration
+            .render(&mut out, "rozsdhabot_fetch_duration_seconds", "");
+
+        header(
+            &mut out,
+            "fetch_responses_total",
+            "counter",
+            "Fetches by HTTP status code, error if there was no response.",
+        );
+        for (status, count) in &self.fetch_responses {
+            let _ = writeln!(
+                out,
+                "rozsdhabot_fetch_responses_total{{status=\"{status}\"}} {count}"
+            );
+        }
+
+        header(
+            &mut out,
+            "parsed_listings_total",
+            "counter",
+            "Listings parsed successfully.",
+        );
+        for (parser, count) in &self.parsed_listings {
+            let _ = writeln!(
+                out,
+                "rozsdhabot_parsed_listings_total{{parser=\"{parser}\"}} {count}"
+            );
+        }
+
+        header(
+            &mut out,
+            "parse_failures_total",
+            "counter",
+            "Fields that could not be parsed, by field and kind.",
+        );
+        for ((parser, field, kind), count) in &self.parse_failures {
+            let _ = writeln!(
+                out,
+                "rozsdhabot_parse_failures_total{{parser=\"{parser}\",field=\"{field}\",kind=\"{kind}\"}} {count}"
+            );
+        }
+
+        header(
+            &mut out,
+            "notifications_total",
+            "counter",
+            "Notifications by integration and result.",
+        );
+        for ((integration, success), count) in &self.notifications {
+            let _ = writeln!(
+                out,
+                "rozsdhabot_notifications_total{{integration=\"{integration}\",result=\"{}\"}} {count}",
+                result(*success)
+            );
+        }
+
+        header(&mut out, "active_monitors", "gauge", "Monitors running.");
+        let _ = writeln!(out, "rozsdhabot_active_monitors {active_monitors}");
+
+        header(
+            &mut out,
+            "persistence_write_duration_seconds",
+            "histogram",
+            "Time taken to save a data file.",
+        );
+        for (file, histogram) in &self.persistence_writes {
+            histogram.render(
+                &mut out,
+                "rozsdhabot_persistence_write_duration_seconds",
+                &format!("file=\"{file}\","),
+            );
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP rozsdhabot_{name} {help}");
+    let _ = writeln!(out, "# TYPE rozsdhabot_{name} {kind}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut metrics = Metrics::default();
+        metrics.record_scrape(3, true);
+        metrics.record_scrape(3, true);
+        metrics.record_scrape(3, false);
+        metrics.record_fetch(Duration::from_millis(300), Some(200));
+        metrics.record_fetch(Duration::from_secs(20), None);
+        metrics.record_parse_failure("hardverapro", Field::Price, ParseFailureKind::Invalid);
+        metrics.record_notification("telegram", false);
+        metrics.record_persistence_write("state", Duration::from_millis(2));
+
+        let text = metrics.render(4);
+        for line in [
+            r#"rozsdhabot_scrapes_total{subscription="3",result="success"} 2"#,
+            r#"rozsdhabot_scrapes_total{subscription="3",result="failure"} 1"#,
+            r#"rozsdhabot_fetch_duration_seconds_bucket{le="0.25"} 0"#,
+            r#"rozsdhabot_fetch_duration_seconds_bucket{le="0.5"} 1"#,
+            r#"rozsdhabot_fetch_duration_seconds_bucket{le="15"} 1"#,
+            r#"rozsdhabot_fetch_duration_seconds_bucket{le="+Inf"} 2"#,
+            r#"rozsdhabot_fetch_duration_seconds_count 2"#,
+            r#"rozsdhabot_fetch_responses_total{status="error"} 1"#,
+            r#"rozsdhabot_parse_failures_total{parser="hardverapro",field="Price",kind="Invalid"} 1"#,
+            r#"rozsdhabot_notifications_total{integration="telegram",result="failure"} 1"#,
+            r#"rozsdhabot_active_monitors 4"#,
+            r#"rozsdhabot_persistence_write_duration_seconds_bucket{file="state",le="0.005"} 1"#,
+            r#"rozsdhabot_persistence_write_duration_seconds_count{file="state"} 1"#,
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {line} in\n{text}");
+        }
+    }
+
+    #[test]
+    fn test_deleted_subscription() {
+        let mut metrics = Metrics::default();
+        metrics.record_scrape(3, true);
+        metrics.record_scrape(3, false);
+        metrics.record_scrape(4, true);
+        metrics.remove_subscription(3);
+
+        let text = metrics.render(1);
+        assert!(!text.contains(r#"subscription="3""#));
+        assert!(text.contains(r#"rozsdhabot_scrapes_total{subscription="4",result="success"} 1"#));
+    }
+
+    #[test]
+    fn test_fetch_timeout_buckets() {
+        let mut metrics = Metrics::default();
+        metrics.set_fetch_timeout(Duration::from_secs(30));
+        metrics.record_fetch(Duration::from_secs(20), Some(200));
+        let text = metrics.render(0);
+        assert!(text.contains(r#"rozsdhabot_fetch_duration_seconds_bucket{le="15"} 0"#));
+        assert!(text.contains(r#"rozsdhabot_fetch_duration_seconds_bucket{le="30"} 1"#));
+
+        metrics.set_fetch_timeout(Duration::from_secs(3));
+        let text = metrics.render(0);
+        assert!(text.contains(r#"rozsdhabot_fetch_duration_seconds_bucket{le="2.5"} 0"#));
+        assert!(text.contains(r#"rozsdhabot_fetch_duration_seconds_bucket{le="3"} 0"#));
+        assert!(!text.contains(r#"le="5""#));
+    }
+}
@@ -0,0 +1,171 @@
+use chrono::{NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{ChannelId, Listing, ScrapeMetadata, Subscription};
+
+/// The timezone HardverApró (and most of our users) live in. Quiet hours are interpreted in this
+/// timezone regardless of where the bot is running.
+pub const SITE_TIMEZONE: Tz = chrono_tz::Europe::Budapest;
+
+/// Settings that apply to a channel as a whole, across all subscriptions in it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelSettings {
+    pub channel: ChannelId,
+    pub quiet_hours: Option<QuietHours>,
+    /// Sellers whose listings are never sent to this channel. Either seller names or seller
+    /// profile URLs.
+    #[serde(default)]
+    pub blocked_sellers: Vec<String>,
+    /// The secret part of the feed URL of the channel. Created on first request.
+    #[serde(default)]
+    pub feed_token: Option<String>,
+    /// Listings waiting for the quiet hours of the channel to end. Saved with the rest, so a
+    /// restart during quiet hours doesn't lose them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub held: Vec<HeldListing>,
+}
+
+impl ChannelSettings {
+    pub fn new(channel: ChannelId) -> Self {
+        Self {
+            channel,
+            quiet_hours: None,
+            blocked_sellers: Vec::new(),
+            feed_token: None,
+            held: Vec::new(),
+        }
+    }
+
+    /// Whether the seller of the listing is on the blocklist. Entries match either the seller's
+    /// name (case-insensitively) or the seller's profile (by URL or by its identifier in the URL).
+    /// `seller_slug` extracts the identifier from a profile URL, it's up to the marketplace.
+    pub fn is_seller_blocked(
+        &self,
+        listing: &Listing,
+        seller_slug: impl Fn(&str) -> Option<String>,
+    ) -> bool {
+        let name = listing.seller_name.to_lowercase();
+        let slug = seller_slug(&listing.seller_url);
+        self.blocked_sellers.iter().any(|blocked| {
+            let blocked = blocked.to_lowercase();
+            blocked == name
+                || slug.as_ref().is_some_and(|slug| {
+                    *slug == blocked || seller_slug(&blocked).as_ref() == Some(slug)
+                })
+        })
+    }
+}
+
+/// A notification that was held back during quiet hours, for a digest, or after failing to send,
+/// to be sent later in a catch-up message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeldListing {
+    pub subscription: Subscription,
+    pub metadata: ScrapeMetadata,
+    pub listing: Listing,
+}
+
+/// What happens to notifications during quiet hours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuietMode {
+    /// Notifications are held back and sent as a single catch-up message when the window ends.
+    Hold,
+    /// Notifications are sent as usual, but without making a sound (where the platform allows).
+    Silent,
+}
+
+/// A daily time window in [`SITE_TIMEZONE`]. The window may wrap around midnight
+/// (e.g. 23:00 - 07:00).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub mode: QuietMode,
+}
+
+impl QuietHours {
+    /// Whether the window contains the given wall clock time. The start is inclusive, the end is
+    /// exclusive.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            // Wraps around midnight
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// Whether the window is active right now.
+    pub fn is_active(&self) -> bool {
+        self.contains(Utc::now().with_timezone(&SITE_TIMEZONE).time())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::seller_slug;
+
+    fn hm(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_seller_blocklist() {
+        let mut settings = ChannelSettings::new(ChannelId::Terminal);
+        let listing = Listing {
+            seller_name: "Kovács.Péter".to_string(),
+            seller_url: "/tag/kovacs_peter_8.html?uadid=4795714".to_string(),
+            ..Listing::dummy()
+        };
+        assert!(!settings.is_seller_blocked(&listing, seller_slug));
+
+        settings.blocked_sellers = vec!["kovács.péter".to_string()];
+        assert!(settings.is_seller_blocked(&listing, seller_slug));
+
+        settings.blocked_sellers =
+            vec!["https://hardverapro.hu/tag/kovacs_peter_8.html".to_string()];
+        assert!(settings.is_seller_blocked(&listing, seller_slug));
+
+        settings.blocked_sellers = vec!["kovacs_peter_8".to_string()];
+        assert!(settings.is_seller_blocked(&listing, seller_slug));
+
+        settings.blocked_sellers = vec!["someone_else".to_string()];
+        assert!(!settings.is_seller_blocked(&listing, seller_slug));
+
+        // Business sellers link to their listings instead of a profile.
+        let business = Listing {
+            seller_name: "StyleBolt".to_string(),
+            seller_url: "/aprok/hirdeto/stylebolt_hu/index.html?uadid=7303718".to_string(),
+            ..Listing::dummy()
+        };
+        settings.blocked_sellers =
+            vec!["https://hardverapro.hu/aprok/hirdeto/stylebolt_hu/index.html".to_string()];
+        assert!(settings.is_seller_blocked(&business, seller_slug));
+        assert!(!settings.is_seller_blocked(&listing, seller_slug));
+    }
+
+    #[test]
+    fn test_quiet_hours_contains() {
+        let overnight = QuietHours {
+            start: hm(23, 0),
+            end: hm(7, 0),
+            mode: QuietMode::Hold,
+        };
+        assert!(overnight.contains(hm(23, 0)));
+        assert!(overnight.contains(hm(2, 30)));
+        assert!(!overnight.contains(hm(7, 0)));
+        assert!(!overnight.contains(hm(12, 0)));
+
+        let daytime = QuietHours {
+            start: hm(9, 0),
+            end: hm(17, 0),
+            mode: QuietMode::Silent,
+        };
+        assert!(daytime.contains(hm(9, 0)));
+        assert!(daytime.contains(hm(16, 59)));
+        assert!(!daytime.contains(hm(17, 0)));
+        assert!(!daytime.contains(hm(3, 0)));
+    }
+}
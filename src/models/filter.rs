@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::Listing;
+
+/// Local filters that are applied to listings before notifying. These are on top of whatever the
+/// search URL already filters for.
+///
+/// Every filter is optional, the default filter lets everything through.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListingFilter {
+    pub min_price: Option<f64>,
+    pub max_price: Option<f64>,
+    /// Minimum number of positive ratings the seller needs to have.
+    pub min_seller_ratings: Option<i64>,
+    /// If not empty, at least one of the listing's cities has to match one of these.
+    pub allowed_cities: Vec<String>,
+    /// Listings are dropped if all of their cities match one of these.
+    pub denied_cities: Vec<String>,
+    pub hide_frozen: bool,
+    /// Hide listings of dealers and shops.
+    pub private_sellers_only: bool,
+}
+
+impl ListingFilter {
+    /// Whether the listing passes every filter. Price filters only apply to listings that have
+    /// a price.
+    ///
+    /// Cities are matched case-insensitively by substring, so "kerület" matches every Budapest
+    /// district.
+    pub fn accepts(&self, listing: &Listing) -> bool {
+        if let Some(price) = listing.price.amount() {
+            if self.min_price.is_some_and(|min| price < min) {
+                return false;
+            }
+            if self.max_price.is_some_and(|max| price > max) {
+                return false;
+            }
+        }
+        if self
+            .min_seller_ratings
+            .is_some_and(|min| listing.seller_ratings < min)
+        {
+            return false;
+        }
+        if self.hide_frozen && listing.frozen {
+            return false;
+        }
+        if self.private_sellers_only && listing.seller_is_business {
+            return false;
+        }
+        if !self.allowed_cities.is_empty()
+            && !listing
+                .cities
+                .iter()
+                .any(|city| matches_any(city, &self.allowed_cities))
+        {
+            return false;
+        }
+        if !self.denied_cities.is_empty()
+            && !listing.cities.is_empty()
+            && listing
+                .cities
+                .iter()
+                .all(|city| matches_any(city, &self.denied_cities))
+        {
+            return false;
+        }
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+fn matches_any(city: &str, patterns: &[String]) -> bool {
+    let city = city.to_lowercase();
+    patterns
+        .iter()
+        .any(|pattern| city.contains(&pattern.to_lowercase()))
+}
+
+impl std::fmt::Display for ListingFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no filters");
+        }
+
+        let mut parts = Vec::new();
+        if let Some(min) = self.min_price {
+            parts.push(format!("min price: {min:.0} Ft"));
+        }
+        if let Some(max) = self.max_price {
+            parts.push(format!("max price: {max:.0} Ft"));
+        }
+        if let Some(min) = self.min_seller_ratings {
+            parts.push(format!("min seller ratings: +{min}"));
+        }
+        if !self.allowed_cities.is_empty() {
+            parts.push(format!("cities: {}", self.allowed_cities.join(", ")));
+        }
+        if !self.denied_cities.is_empty() {
+            parts.push(format!(
+                "excluded cities: {}",
+                self.denied_cities.join(", ")
+            ));
+        }
+        if self.hide_frozen {
+            parts.push("frozen listings hidden".to_string());
+        }
+        if self.private_sellers_only {
+            parts.push("private sellers only".to_string());
+        }
+        write!(f, "{}", parts.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Price;
+
+    fn listing(price: f64, seller_ratings: i64, cities: &[&str], frozen: bool) -> Listing {
+        Listing {
+            price: Price::Fixed(price),
+            seller_ratings,
+            cities: cities.iter().map(|c| c.to_string()).collect(),
+            frozen,
+            ..Listing::dummy()
+        }
+    }
+
+    #[test]
+    fn test_default_filter_accepts_everything() {
+        let filter = ListingFilter::default();
+        assert!(filter.accepts(&listing(0.0, 0, &[], true)));
+        assert!(filter.accepts(&listing(1e9, 0, &["Szeged"], false)));
+    }
+
+    #[test]
+    fn test_price_rating_and_frozen_filters() {
+        let filter = ListingFilter {
+            min_price: Some(10000.0),
+            max_price: Some(50000.0),
+            min_seller_ratings: Some(5),
+            hide_frozen: true,
+            ..Default::default()
+        };
+        assert!(filter.accepts(&listing(20000.0, 5, &["Pécs"], false)));
+        assert!(!filter.accepts(&listing(9999.0, 5, &["Pécs"], false)));
+        assert!(!filter.accepts(&listing(50001.0, 5, &["Pécs"], false)));
+        assert!(!filter.accepts(&listing(20000.0, 4, &["Pécs"], false)));
+        assert!(!filter.accepts(&listing(20000.0, 5, &["Pécs"], true)));
+
+        // Listings without a price are not affected by price filters.
+        let wanted = Listing {
+            price: Price::Wanted,
+            seller_ratings: 5,
+            ..Listing::dummy()
+        };
+        assert!(filter.accepts(&wanted));
+    }
+
+    #[test]
+    fn test_private_sellers_only() {
+        let filter = ListingFilter {
+            private_sellers_only: true,
+            ..Default::default()
+        };
+        let business = Listing {
+            seller_is_business: true,
+            ..Listing::dummy()
+        };
+        assert!(!filter.accepts(&business));
+        assert!(filter.accepts(&Listing::dummy()));
+    }
+
+    #[test]
+    fn test_city_filters() {
+        let budapest_only = ListingFilter {
+            allowed_cities: vec!["budapest".to_string(), "kerület".to_string()],
+            ..Default::default()
+        };
+        assert!(budapest_only.accepts(&listing(1.0, 0, &["VII. kerület"], false)));
+        assert!(budapest_only.accepts(&listing(1.0, 0, &["Budapest", "Dunaharaszti"], false)));
+        assert!(!budapest_only.accepts(&listing(1.0, 0, &["Szeged"], false)));
+
+        let no_pecs = ListingFilter {
+            denied_cities: vec!["Pécs".to_string()],
+            ..Default::default()
+        };
+        assert!(!no_pecs.accepts(&listing(1.0, 0, &["Pécs"], false)));
+        // Still available somewhere else.
+        assert!(no_pecs.accepts(&listing(1.0, 0, &["Pécs", "Szigetvár"], false)));
+    }
+}
@@ -1,6 +1,7 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ListingType {
     Featured,
     Bazar,
@@ -8,16 +9,53 @@ pub enum ListingType {
 }
 pub type ListingId = i64;
 
-#[derive(Debug)]
+/// What the seller asks for the item.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Price {
+    Fixed(f64),
+    Free,
+    /// The seller wants to trade the item for something else.
+    Swap,
+    /// Not a sale, someone is looking to buy the item.
+    Wanted,
+}
+
+impl Price {
+    /// The price in forints, if the listing is a sale. Free listings cost 0.
+    pub fn amount(&self) -> Option<f64> {
+        match self {
+            Price::Fixed(amount) => Some(*amount),
+            Price::Free => Some(0.0),
+            Price::Swap | Price::Wanted => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Price {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Price::Fixed(amount) => write!(f, "{amount:.0} Ft"),
+            Price::Free => write!(f, "Free"),
+            Price::Swap => write!(f, "Swap"),
+            Price::Wanted => write!(f, "Wanted"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Listing {
     // Should be unique
     pub id: ListingId,
-    // pub img_url: String,
+    pub img_url: Option<String>,
     pub url: String,
     pub title: String,
-    pub price: f64,
+    pub price: Price,
     pub cities: Vec<String>,
-    pub date: NaiveDateTime,
+    /// Bumped listings don't show their date.
+    pub date: Option<DateTime<Utc>>,
+    /// The listing was moved to the top of the results by the seller ("Előresorolva"). Bumped
+    /// listings can be old ones.
+    pub bumped: bool,
 
     pub frozen: bool,
 
@@ -26,6 +64,95 @@ pub struct Listing {
     // We only store the positive ratings. Only that matters mostly on hardverapro.
     pub seller_ratings: i64,
     pub seller_url: String,
+    /// Dealers and shops, as opposed to private sellers.
+    pub seller_is_business: bool,
 
     pub listing_type: ListingType,
+
+    /// Only filled in if the subscription has enrichment enabled and the detail page could be
+    /// parsed.
+    pub details: Option<ListingDetails>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Condition {
+    New,
+    Used,
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::New => write!(f, "new"),
+            Condition::Used => write!(f, "used"),
+        }
+    }
+}
+
+/// Information that is only available on the detail page of a listing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ListingDetails {
+    pub description: String,
+    pub condition: Option<Condition>,
+    /// As written by the seller, e.g. "2026-03-01-ig" or "nincs".
+    pub warranty: Option<String>,
+    pub shipping: Vec<String>,
+    /// Every image of the listing, in the order they are shown.
+    pub images: Vec<String>,
+}
+
+impl ListingDetails {
+    /// The first `max_chars` characters of the description, cut at a word boundary.
+    pub fn description_excerpt(&self, max_chars: usize) -> String {
+        let description = self
+            .description
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if description.chars().count() <= max_chars {
+            return description;
+        }
+
+        let cut: String = description.chars().take(max_chars).collect();
+        match cut.rfind(' ') {
+            Some(space) => format!("{}...", &cut[..space]),
+            None => format!("{cut}..."),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Listing {
+    /// A listing with placeholder values, for tests to modify as they need.
+    pub fn dummy() -> Self {
+        Self {
+            id: 1,
+            img_url: None,
+            url: "https://hardverapro.hu/apro/test/friss.html".to_string(),
+            title: "test".to_string(),
+            price: Price::Free,
+            cities: Vec::new(),
+            date: chrono::NaiveDate::from_ymd_opt(2025, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .map(|t| t.and_utc()),
+            bumped: false,
+            frozen: false,
+            seller_name: "seller".to_string(),
+            seller_ratings: 0,
+            seller_url: "/tag/seller.html".to_string(),
+            seller_is_business: false,
+            listing_type: ListingType::Regular,
+            details: None,
+        }
+    }
+}
+
+/// What the search page says about the search itself.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScrapeMetadata {
+    pub name: Option<String>,
+    pub category: Option<String>,
+    pub min_price: Option<f64>,
+    pub max_price: Option<f64>,
 }
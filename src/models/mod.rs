@@ -1,5 +1,18 @@
+mod channel;
+mod filter;
 mod listing;
 mod subscription;
+mod timestamp;
 
-pub use listing::{Listing, ListingId, ListingType};
-pub use subscription::{ChannelId, OwnerId, Subscription, SubscriptionConfig, SubscriptionState};
+pub use channel::{ChannelSettings, HeldListing, QuietHours, QuietMode, SITE_TIMEZONE};
+pub use filter::ListingFilter;
+pub use listing::{
+    Condition, Listing, ListingDetails, ListingId, ListingType, Price, ScrapeMetadata,
+};
+pub use subscription::{
+    ChannelId, FoundListing, OwnerId, RecentListings, ScrapeStatus, Subscription,
+    SubscriptionConfig, SubscriptionMetrics, SubscriptionState, set_default_interval,
+};
+pub use timestamp::{
+    deserialize_timestamp, format_timestamp, set_display_timezone, site_time_to_utc,
+};
@@ -1,10 +1,12 @@
-use chrono::NaiveDateTime;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::models::listing::ListingId;
+use crate::models::{Listing, ListingFilter, Price, listing::ListingId};
 
 /// Contains the information needed to identify a channel. Differs for different platforms.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ChannelId {
     // This is always just stdout.
     Terminal,
@@ -15,6 +17,54 @@ pub enum ChannelId {
     Discord {
         channel: serenity::model::id::ChannelId,
     },
+    /// The configured webhook URL. There is only one, so this channel is global: every chat that
+    /// enables the webhook for a subscription sends to the same URL, and shares its channel
+    /// settings (quiet hours, blocked sellers).
+    Webhook,
+    Email {
+        address: String,
+    },
+    Matrix {
+        room_id: String,
+    },
+    Ntfy {
+        topic: String,
+    },
+    /// Subscriptions created through the REST API or the dashboard. Nothing is sent here, the
+    /// listings are read back through the API.
+    Api,
+}
+
+impl ChannelId {
+    /// The name of the integration that sends to the channel.
+    pub fn platform(&self) -> &'static str {
+        match self {
+            ChannelId::Terminal => "terminal",
+            ChannelId::Telegram { .. } => "telegram",
+            ChannelId::Discord { .. } => "discord",
+            ChannelId::Webhook => "webhook",
+            ChannelId::Email { .. } => "email",
+            ChannelId::Matrix { .. } => "matrix",
+            ChannelId::Ntfy { .. } => "ntfy",
+            ChannelId::Api => "api",
+        }
+    }
+}
+
+/// Written as `platform:id`, the way channels are given in the configuration.
+impl std::fmt::Display for ChannelId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelId::Terminal => write!(f, "terminal"),
+            ChannelId::Telegram { chat_id } => write!(f, "telegram:{chat_id}"),
+            ChannelId::Discord { channel } => write!(f, "discord:{channel}"),
+            ChannelId::Webhook => write!(f, "webhook"),
+            ChannelId::Email { address } => write!(f, "email:{address}"),
+            ChannelId::Matrix { room_id } => write!(f, "matrix:{room_id}"),
+            ChannelId::Ntfy { topic } => write!(f, "ntfy:{topic}"),
+            ChannelId::Api => write!(f, "api"),
+        }
+    }
 }
 
 // /// Might be expanded in the future.
@@ -33,21 +83,67 @@ pub struct SubscriptionConfig {
     pub show_bazar: bool,
     pub show_featured: bool,
     pub show_regular: bool,
+
+    /// Local filters applied before notifying.
+    #[serde(default)]
+    pub filter: ListingFilter,
+
+    /// Fetch the detail page of new listings, for a richer notification. Costs an extra request
+    /// per new listing.
+    #[serde(default)]
+    pub enrich: bool,
+
+    /// Notify for listings that offer a swap instead of a price.
+    #[serde(default)]
+    pub show_swaps: bool,
+    /// Notify for "wanted" posts, where someone is looking to buy.
+    #[serde(default)]
+    pub show_wanted: bool,
+    /// Notify when an older listing is bumped to the top of the results.
+    #[serde(default)]
+    pub show_bumped: bool,
+
+    /// Paused subscriptions are kept, but their monitor isn't running.
+    #[serde(default)]
+    pub paused: bool,
+}
+
+/// Set once on startup from the configuration.
+static DEFAULT_INTERVAL: OnceLock<u64> = OnceLock::new();
+
+/// Sets the interval of new subscriptions, in seconds. Only the first call has an effect.
+pub fn set_default_interval(interval: u64) {
+    let _ = DEFAULT_INTERVAL.set(interval);
 }
 
 impl SubscriptionConfig {
     pub fn default() -> Self {
         Self {
-            interval: 60,
+            interval: DEFAULT_INTERVAL.get().copied().unwrap_or(60),
             show_bazar: false,
             show_featured: true,
             show_regular: true,
+            filter: ListingFilter::default(),
+            enrich: false,
+            show_swaps: false,
+            show_wanted: false,
+            show_bumped: false,
+            paused: false,
+        }
+    }
+
+    /// Whether the kind of the listing (swap, wanted) should be notified for.
+    pub fn shows_kind(&self, listing: &Listing) -> bool {
+        match listing.price {
+            Price::Swap => self.show_swaps,
+            Price::Wanted => self.show_wanted,
+            Price::Fixed(_) | Price::Free => true,
         }
     }
 }
 
 /// Different types of owners for different adapters.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum OwnerId {
     Telegram {
         user_id: Option<teloxide::types::UserId>,
@@ -55,6 +151,13 @@ pub enum OwnerId {
     Discord {
         user_id: serenity::model::id::UserId,
     },
+    Matrix {
+        user_id: String,
+    },
+    /// Anyone holding an API token, or the dashboard password.
+    Api,
+    /// Whoever is typing into the terminal the bot runs in.
+    Terminal,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -79,13 +182,103 @@ pub struct Subscription {
     /// Platform specific configuration.
     // pub platform_config: ChannelConfig,
 
-    /// Light telemetry
-    // pub metrics: SubscriptionMetrics,
-    pub created_at: NaiveDateTime,
+    // Metrics are in `SubscriptionState`, they change on every scrape.
+    #[serde(deserialize_with = "crate::models::deserialize_timestamp")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+impl Subscription {
+    /// A subscription with placeholder values, for tests to modify as they need.
+    pub fn dummy() -> Self {
+        Self {
+            id: 1,
+            name: Some("gpu".to_string()),
+            channels: Vec::new(),
+            owner: OwnerId::Terminal,
+            url: "https://hardverapro.hu/aprok/hardver/videokartya/index.html".to_string(),
+            config: SubscriptionConfig::default(),
+            created_at: Utc::now(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubscriptionState {
     pub subscription_id: u64,
     pub last_seen: Option<ListingId>,
+    /// Bumped listings that were on the page at the last scrape. Bumped listings have old IDs, so
+    /// `last_seen` can't tell if we have already seen them.
+    #[serde(default)]
+    pub seen_bumped: Vec<ListingId>,
+    /// The secret part of the feed URL of the subscription. Created on first request.
+    #[serde(default)]
+    pub feed_token: Option<String>,
+    #[serde(default)]
+    pub metrics: SubscriptionMetrics,
+}
+
+impl SubscriptionState {
+    pub fn new(subscription_id: u64) -> Self {
+        Self {
+            subscription_id,
+            last_seen: None,
+            seen_bumped: Vec::new(),
+            feed_token: None,
+            metrics: SubscriptionMetrics::default(),
+        }
+    }
+}
+
+/// Lifetime counters of a subscription, shown by `/info`. Saved with the rest of the state, on
+/// the next write or every few minutes, so the last few updates can be lost on a crash.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SubscriptionMetrics {
+    /// When the first scrape was recorded. Averages are calculated from this.
+    pub since: Option<DateTime<Utc>>,
+    pub total_scrapes: u64,
+    pub failed_scrapes: u64,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+    /// New listings that matched the subscription, before the blocklists of the channels.
+    pub listings_found: u64,
+    /// Notifications sent successfully, to every channel together.
+    pub notifications_sent: u64,
+}
+
+impl SubscriptionMetrics {
+    /// The average number of new listings per day. The first day counts as a whole day, so a
+    /// new subscription doesn't show a huge average.
+    pub fn listings_per_day(&self, now: DateTime<Utc>) -> Option<f64> {
+        let since = self.since?;
+        let days = (now - since).num_seconds() as f64 / (24.0 * 60.0 * 60.0);
+        Some(self.listings_found as f64 / days.max(1.0))
+    }
+}
+
+/// How the scrapes of a subscription went since the bot was started. Not saved.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScrapeStatus {
+    pub last_scrape: Option<DateTime<Utc>>,
+    pub last_success: Option<DateTime<Utc>>,
+    /// Reset by a successful scrape.
+    pub consecutive_failures: u32,
+    pub total_failures: u64,
+    pub last_error: Option<String>,
+}
+
+/// The latest listings found by a subscription, newest first. These are served as its feed.
+/// Saved apart from `SubscriptionState`, since they are much bigger and change more often.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentListings {
+    pub subscription_id: u64,
+    pub listings: Vec<FoundListing>,
+}
+
+/// A listing that was found by a subscription, and when.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FoundListing {
+    pub found_at: DateTime<Utc>,
+    pub listing: Listing,
 }
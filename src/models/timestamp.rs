@@ -0,0 +1,124 @@
+//! Timestamps are stored in UTC, and only converted to a timezone when they are shown to users.
+
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer};
+
+use crate::models::SITE_TIMEZONE;
+
+/// Set once on startup from the configuration.
+static DISPLAY_TIMEZONE: OnceLock<Tz> = OnceLock::new();
+
+/// Sets the timezone timestamps are shown in. Only the first call has an effect.
+pub fn set_display_timezone(timezone: Tz) {
+    let _ = DISPLAY_TIMEZONE.set(timezone);
+}
+
+/// The timezone timestamps are shown in. Budapest time unless configured otherwise.
+pub fn display_timezone() -> Tz {
+    DISPLAY_TIMEZONE.get().copied().unwrap_or(SITE_TIMEZONE)
+}
+
+/// Formats a timestamp in the display timezone.
+pub fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp
+        .with_timezone(&display_timezone())
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+/// Converts a time read from the site (Budapest local time) to UTC.
+///
+/// When the clocks go back, the ambiguous hour is taken as the earlier one. Times skipped when
+/// the clocks go forward don't exist, those are moved forward by an hour.
+pub fn site_time_to_utc(time: NaiveDateTime) -> DateTime<Utc> {
+    SITE_TIMEZONE
+        .from_local_datetime(&time)
+        .earliest()
+        .or_else(|| {
+            SITE_TIMEZONE
+                .from_local_datetime(&(time + TimeDelta::hours(1)))
+                .earliest()
+        })
+        .map(|t| t.to_utc())
+        // Every timezone has at most a one hour gap.
+        .unwrap_or_else(|| time.and_utc())
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredTimestamp {
+    Utc(DateTime<Utc>),
+    /// Older versions saved the local time of the machine without a timezone.
+    Naive(NaiveDateTime),
+}
+
+/// Deserializes a UTC timestamp, accepting the naive local timestamps saved by older versions.
+pub fn deserialize_timestamp<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<DateTime<Utc>, D::Error> {
+    Ok(match StoredTimestamp::deserialize(deserializer)? {
+        StoredTimestamp::Utc(timestamp) => timestamp,
+        // These were written with `Local::now()` on (most likely) the same machine.
+        StoredTimestamp::Naive(time) => Local
+            .from_local_datetime(&time)
+            .earliest()
+            .map(|t| t.to_utc())
+            .unwrap_or_else(|| time.and_utc()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[derive(Deserialize)]
+    struct Stored {
+        #[serde(deserialize_with = "deserialize_timestamp")]
+        created_at: DateTime<Utc>,
+    }
+
+    #[test]
+    fn test_migrate_naive_timestamp() {
+        let naive = NaiveDate::from_ymd_opt(2025, 7, 1)
+            .unwrap()
+            .and_hms_opt(12, 30, 0)
+            .unwrap();
+
+        let old: Stored = serde_json::from_str(r#"{"created_at":"2025-07-01T12:30:00.123"}"#)
+            .expect("naive timestamps should still load");
+        let expected = Local.from_local_datetime(&naive).earliest().unwrap();
+        assert_eq!(old.created_at.timestamp(), expected.to_utc().timestamp());
+
+        let new: Stored = serde_json::from_str(r#"{"created_at":"2025-07-01T12:30:00Z"}"#).unwrap();
+        assert_eq!(new.created_at, naive.and_utc());
+    }
+
+    #[test]
+    fn test_site_time_to_utc() {
+        let at = |month, day, hour, minute| {
+            NaiveDate::from_ymd_opt(2025, month, day)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap()
+        };
+        // Summer time is UTC+2, winter time is UTC+1.
+        assert_eq!(site_time_to_utc(at(7, 1, 12, 0)), at(7, 1, 10, 0).and_utc());
+        assert_eq!(
+            site_time_to_utc(at(12, 1, 12, 0)),
+            at(12, 1, 11, 0).and_utc()
+        );
+        // 02:30 happens twice when the clocks go back, and not at all when they go forward.
+        assert_eq!(
+            site_time_to_utc(at(10, 26, 2, 30)),
+            at(10, 26, 0, 30).and_utc()
+        );
+        assert_eq!(
+            site_time_to_utc(at(3, 30, 2, 30)),
+            at(3, 30, 1, 30).and_utc()
+        );
+    }
+}
@@ -2,12 +2,18 @@ use tokio::{sync::watch, task::JoinHandle};
 use tracing::Instrument;
 
 use crate::{
-    fetcher::Fetcher, integrations::NotifierRegistry, models::Subscription,
-    parsers::parse_hardverapro, storage::RuntimeStateStore,
+    fetcher::Fetcher,
+    health::{PARSER_HEALTH, dump_page},
+    integrations::NotifierRegistry,
+    metrics::METRICS,
+    models::{ChannelId, HeldListing, Listing, ListingId, QuietMode, ScrapeMetadata, Subscription},
+    parsers::{MarketplaceParser, ParseFailureKind, parser_for, seller_slug},
+    storage::{ChannelStore, RuntimeStateStore},
 };
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 /// A monitor is responsible for running one subscription.
@@ -15,32 +21,70 @@ pub struct Monitor {
     subscription: Subscription,
     fetcher: Fetcher,
     runtime_store: Arc<Mutex<RuntimeStateStore>>,
+    channel_store: Arc<Mutex<ChannelStore>>,
     notifiers: NotifierRegistry,
+    settings: MonitorSettings,
+}
+
+/// The same for every monitor, set from the configuration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MonitorSettings {
+    /// Time between two notifications of a scrape. Telegram is pretty lenient with rate limiting.
+    pub notify_stagger: Duration,
+    /// Fetches taking longer than this fail.
+    pub fetch_timeout: Duration,
+}
+
+impl Default for MonitorSettings {
+    fn default() -> Self {
+        Self {
+            notify_stagger: Duration::from_millis(100),
+            fetch_timeout: Duration::from_secs(15),
+        }
+    }
 }
 
 #[derive(Default)]
 pub struct MonitorManager {
     monitors: HashMap<u64, MonitorHandle>,
+    settings: MonitorSettings,
 }
 
 /// A monitor manager that is reponsible for spawning and keeping track of monitors.
 impl MonitorManager {
-    pub fn new(monitors: HashMap<u64, MonitorHandle>) -> Self {
-        Self { monitors }
+    pub fn new(settings: MonitorSettings) -> Self {
+        Self {
+            monitors: HashMap::new(),
+            settings,
+        }
     }
 
     pub fn start_monitor(
         &mut self,
         subscription: Subscription,
         runtime_store: Arc<Mutex<RuntimeStateStore>>,
+        channel_store: Arc<Mutex<ChannelStore>>,
         notifiers: NotifierRegistry,
     ) {
+        if subscription.config.paused {
+            tracing::info!("Subscription {} is paused, not starting", subscription.id);
+            return;
+        }
+
+        runtime_store.lock().unwrap().add(subscription.id);
+
         // We always want to see which monitor this is.
         let span = tracing::error_span!("monitor", sub = subscription.id);
 
         let (shutdown_tx, shutdown_rx) = watch::channel(());
 
-        let mut monitor = Monitor::new(notifiers, runtime_store, subscription);
+        let mut monitor = Monitor::new(
+            notifiers,
+            runtime_store,
+            channel_store,
+            subscription,
+            self.settings,
+        );
         let id = monitor.subscription.id;
 
         let join = tokio::spawn(async move {
@@ -65,15 +109,32 @@ impl MonitorManager {
         }
     }
 
+    /// Whether the monitor of the subscription is running. Monitors keep running through scrape
+    /// errors, they only stop on their own if they panic.
+    pub fn is_running(&self, id: u64) -> bool {
+        self.monitors
+            .get(&id)
+            .is_some_and(|handle| !handle.join.is_finished())
+    }
+
+    /// The number of monitors that are running.
+    pub fn running_count(&self) -> usize {
+        self.monitors
+            .values()
+            .filter(|handle| !handle.join.is_finished())
+            .count()
+    }
+
     /// In the current implementation subscriptions are restarted when modified.
     pub fn restart_monitor(
         &mut self,
         subscription: Subscription,
         runtime_store: Arc<Mutex<RuntimeStateStore>>,
+        channel_store: Arc<Mutex<ChannelStore>>,
         notifiers: NotifierRegistry,
     ) {
         self.stop_monitor(subscription.id);
-        self.start_monitor(subscription, runtime_store, notifiers);
+        self.start_monitor(subscription, runtime_store, channel_store, notifiers);
     }
 }
 
@@ -86,15 +147,19 @@ impl Monitor {
     pub fn new(
         notifiers: NotifierRegistry,
         runtime_store: Arc<Mutex<RuntimeStateStore>>,
+        channel_store: Arc<Mutex<ChannelStore>>,
         subscription: Subscription,
+        settings: MonitorSettings,
     ) -> Self {
         Self {
             // Contains the configuration for the subscription.
             subscription,
             runtime_store,
+            channel_store,
             // A new fetcher that is inconsistent across different runs. This is on purpose.
-            fetcher: Fetcher::new(),
+            fetcher: Fetcher::new(settings.fetch_timeout),
             notifiers,
+            settings,
         }
     }
 
@@ -103,35 +168,64 @@ impl Monitor {
         let mut interval = tokio::time::interval(std::time::Duration::from_secs(
             self.subscription.config.interval,
         ));
+        // Fetches can be held up by the rate limiting of the fetcher. We don't want to make up
+        // for the lost time with a burst of scrapes.
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
         loop {
             tokio::select! {
                 _ = shutdown.changed() => {tracing::info!("Monitor stopping"); break},
                 // errors are ignored here.
-                _ = interval.tick() => self.scrape().await.unwrap_or(()),
+                _ = interval.tick() => {
+                    let result = self.scrape().await;
+                    if let Err(e) = &result {
+                        tracing::error!("{e}");
+                    }
+                    METRICS
+                        .lock()
+                        .unwrap()
+                        .record_scrape(self.subscription.id, result.is_ok());
+                    self.runtime_store
+                        .lock()
+                        .unwrap()
+                        .record_scrape(self.subscription.id, result);
+                }
             }
         }
     }
 
-    /// This is where the magic happens.
-    async fn scrape(&self) -> Result<(), ()> {
-        // Telegram is pretty lenient with rate limiting
-        const NOTIFY_STAGGER: std::time::Duration = std::time::Duration::from_millis(100);
-
+    /// This is where the magic happens. Errors are logged by the caller.
+    async fn scrape(&self) -> Result<(), String> {
         tracing::debug!("scraping...");
 
+        let Some(parser) = parser_for(&self.subscription.url) else {
+            return Err(format!("No parser for URL: {}", self.subscription.url));
+        };
+
         let body = match self.fetcher.fetch(&self.subscription.url).await {
             Ok(body) => body,
-            Err(e) => {
-                tracing::error!("Failed to fetch site: {e}. Possibly invalid URL.");
-                return Err(());
-            }
+            Err(e) => return Err(format!("Failed to fetch site: {e}. Possibly invalid URL.")),
         };
 
         // Where the parser is run.
-        // Future expansion: We could run a parser based on the type of subscription (implementing
-        // multiple parsers).
-        let page = parse_hardverapro(&body);
+        let page = parser.parse_page(&body);
+        METRICS.lock().unwrap().record_page(parser.name(), &page);
+
+        // Layout changes show up as failures on the pages of every subscription.
+        let (alert, dump_dir) = {
+            let mut health = PARSER_HEALTH.lock().unwrap();
+            (
+                health.record(parser.name(), self.subscription.id, &page),
+                health.dump_dir(),
+            )
+        };
+        if let Some(mut alert) = alert {
+            if let Some(dir) = dump_dir {
+                alert.dump_path = dump_page(&dir, parser.name(), &body).await;
+            }
+            tracing::error!("Parser health alert: {alert}");
+            self.notifiers.notify_admin(&alert.to_string()).await;
+        }
 
         // logging the results
         if page.listings.is_empty() {
@@ -139,9 +233,10 @@ impl Monitor {
                 tracing::warn!("No listings found on page at all. URL to scrape may be incorrect");
             } else {
                 tracing::warn!(
-                    "Failed to parse any listings. {} skips, and {} unparsable fields.",
+                    "Failed to parse any listings. {} skips, {} missing and {} invalid fields.",
                     page.skipped_listings_count(),
-                    page.unparsable_listing_count()
+                    page.missing_field_listings_count(),
+                    page.invalid_filed_listing_count()
                 );
             }
         } else {
@@ -165,27 +260,21 @@ impl Monitor {
             .get(self.subscription.id)
             .cloned();
 
-        match state {
-            Some(..) => {}
-            // If we didn't find an entry for our subscription, we create one.
-            None => {
-                let newest = page.listings.iter().map(|l| l.id).max();
-                if let Some(id) = newest {
-                    self.runtime_store
-                        .lock()
-                        .unwrap()
-                        .update_last_seen(self.subscription.id, id)
-                        .unwrap();
-                }
-                // TODO: This is incorrect logic for what I want to do. This doesn't notify until
-                // it has seen a new listing. I only want it to skip notifying on the first run.
-                tracing::info!("no listing seen before: notifications will not be sent");
-                return Ok(());
-            }
-        };
+        // Remembered on every run, so that bumped listings are only notified for once.
+        let bumped: Vec<ListingId> = page
+            .listings
+            .iter()
+            .filter(|l| l.bumped)
+            .map(|l| l.id)
+            .collect();
 
-        // unwrap is safe because we just checked if it was Some
-        let last_seen = match state.unwrap().last_seen {
+        // The state is created when the monitor starts, so it's only missing if the subscription
+        // was deleted during the scrape.
+        let Some(state) = state else {
+            tracing::debug!("Subscription was deleted, dropping the scrape");
+            return Ok(());
+        };
+        let last_seen = match state.last_seen {
             Some(last_seen) => last_seen,
             // If there was no last seen, we update it.
             None => {
@@ -197,12 +286,51 @@ impl Monitor {
                         .update_last_seen(self.subscription.id, id)
                         .unwrap();
                 }
+                self.remember_bumped(bumped);
                 tracing::info!("no listing seen before: notifications will not be sent");
                 return Ok(());
             }
         };
 
+        let config = &self.subscription.config;
+        let mut new_listings: Vec<Listing> = page
+            .listings
+            .iter()
+            .filter(|l| {
+                let is_new = l.id > last_seen
+                    || (config.show_bumped && l.bumped && !state.seen_bumped.contains(&l.id));
+                is_new && config.shows_kind(l) && config.filter.accepts(l)
+            })
+            .cloned()
+            .collect();
+
+        // Detail pages count against the crawl delay, so they are only fetched if they can be
+        // parsed.
+        if self.subscription.config.enrich && parser.supports_details() {
+            for listing in &mut new_listings {
+                self.enrich(parser, listing).await;
+            }
+        }
+
+        // Kept for the feeds, regardless of which channels the listings end up in.
+        if let Err(e) = self
+            .runtime_store
+            .lock()
+            .unwrap()
+            .record_found(self.subscription.id, &new_listings)
+        {
+            tracing::error!("Failed to save recent listings: {e}");
+        }
+
+        // A failing channel (e.g. a webhook that is down) doesn't stop the others. Its failed
+        // listings are held and retried by the catch-up task, so the other channels don't get
+        // them twice.
+        let mut failures: Vec<String> = Vec::new();
         for channel in &self.subscription.channels {
+            // API clients read the recent listings instead.
+            if *channel == ChannelId::Api {
+                continue;
+            }
             let Some(notifier) = self.notifiers.notifier_for(channel) else {
                 tracing::warn!(
                     "No notifier for channel: {channel:?}. You should enable the integration for it."
@@ -210,16 +338,81 @@ impl Monitor {
                 continue;
             };
 
-            let channel = *channel;
+            // Digests are sent by the catch-up task too, so they are held like in quiet hours.
+            let digest = notifier.digest_interval().is_some();
+            let (quiet_mode, settings) = {
+                let channel_store = self.channel_store.lock().unwrap();
+                (
+                    channel_store.active_quiet_mode(channel),
+                    channel_store.get(channel).cloned(),
+                )
+            };
 
-            for listing in page.listings.iter().filter(|l| l.id > last_seen) {
-                notifier
-                    .notify_new_listing(&self.subscription, &page.metadata, listing, channel)
-                    .await
-                    .map_err(|e| {
-                        tracing::error!("Failed to notify for listing {listing:?}: {e}");
-                    })?;
-                tokio::time::sleep(NOTIFY_STAGGER).await;
+            let mut failed = 0;
+            for listing in new_listings.iter().filter(|l| {
+                !settings
+                    .as_ref()
+                    .is_some_and(|s| s.is_seller_blocked(l, seller_slug))
+            }) {
+                let result = match quiet_mode {
+                    // Held listings are sent by the catch-up task once the quiet hours are over,
+                    // or with the next digest.
+                    _ if digest => {
+                        tracing::debug!("Holding listing {} for the next digest", listing.id);
+                        self.hold(channel, &page.metadata, listing);
+                        continue;
+                    }
+                    Some(QuietMode::Hold) => {
+                        tracing::debug!("Quiet hours: holding listing {}", listing.id);
+                        self.hold(channel, &page.metadata, listing);
+                        continue;
+                    }
+                    Some(QuietMode::Silent) => {
+                        notifier
+                            .notify_new_listing_silently(
+                                &self.subscription,
+                                &page.metadata,
+                                listing,
+                                channel.clone(),
+                            )
+                            .await
+                    }
+                    None => {
+                        notifier
+                            .notify_new_listing(
+                                &self.subscription,
+                                &page.metadata,
+                                listing,
+                                channel.clone(),
+                            )
+                            .await
+                    }
+                };
+                METRICS
+                    .lock()
+                    .unwrap()
+                    .record_notification(channel.platform(), result.is_ok());
+                match result {
+                    Ok(()) => self
+                        .runtime_store
+                        .lock()
+                        .unwrap()
+                        .record_notification(self.subscription.id),
+                    // `last_seen` moves past the listing regardless, so it is retried by the
+                    // catch-up task instead of the next scrape.
+                    Err(e) => {
+                        tracing::error!(
+                            "Failed to notify {channel:?} of listing {}, holding it for a retry: {e}",
+                            listing.id
+                        );
+                        self.hold(channel, &page.metadata, listing);
+                        failed += 1;
+                    }
+                }
+                tokio::time::sleep(self.settings.notify_stagger).await;
+            }
+            if failed > 0 {
+                failures.push(format!("{failed} to {channel}"));
             }
         }
 
@@ -230,11 +423,142 @@ impl Monitor {
                 .update_last_seen(self.subscription.id, new_last_seen)
                 .unwrap();
         }
+        self.remember_bumped(bumped);
+
+        match failures.is_empty() {
+            true => Ok(()),
+            false => Err(format!(
+                "Failed to send notifications, held for a retry: {}",
+                failures.join(", ")
+            )),
+        }
+    }
+
+    fn hold(&self, channel: &ChannelId, metadata: &ScrapeMetadata, listing: &Listing) {
+        if let Err(e) = self.channel_store.lock().unwrap().hold(
+            channel.clone(),
+            HeldListing {
+                subscription: self.subscription.clone(),
+                metadata: metadata.clone(),
+                listing: listing.clone(),
+            },
+        ) {
+            tracing::error!("Failed to save held listing {}: {e}", listing.id);
+        }
+    }
+
+    fn remember_bumped(&self, bumped: Vec<ListingId>) {
+        if let Err(e) = self
+            .runtime_store
+            .lock()
+            .unwrap()
+            .update_seen_bumped(self.subscription.id, bumped)
+        {
+            tracing::error!("Failed to save bumped listings: {e}");
+        }
+    }
+
+    /// Fetches the detail page of the listing and adds the details to it. Failures are logged,
+    /// and the listing is left as it was.
+    async fn enrich(&self, parser: &dyn MarketplaceParser, listing: &mut Listing) {
+        let body = match self.fetcher.fetch(&listing.url).await {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::warn!("Failed to fetch details of listing {}: {e}", listing.id);
+                return;
+            }
+        };
 
-        Ok(())
+        match parser.parse_details(&body) {
+            Ok(details) => listing.details = Some(details),
+            Err(failure) if failure.kind == ParseFailureKind::Skipped => {}
+            Err(failure) => {
+                METRICS.lock().unwrap().record_parse_failure(
+                    parser.name(),
+                    failure.field,
+                    failure.kind,
+                );
+                tracing::warn!(
+                    "Failed to parse details of listing {}: {} is {:?}",
+                    listing.id,
+                    failure.field,
+                    failure.kind
+                );
+            }
+        }
     }
 
     // async fn filter_new_listings(&self, page: Page) -> Page {}
 
     // async fn notify(&self, page: Page) {}
 }
+
+/// Periodically sends the listings that were held back during quiet hours to the channels whose
+/// quiet hours have ended, and the digests of the channels whose notifier sends digests. Runs for
+/// the lifetime of the application.
+pub async fn run_catch_up(
+    channel_store: Arc<Mutex<ChannelStore>>,
+    runtime_store: Arc<Mutex<RuntimeStateStore>>,
+    notifiers: NotifierRegistry,
+) {
+    const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    // When the last digest was sent to each channel. The first digest after a start waits a full
+    // interval too.
+    let mut digests_sent: HashMap<ChannelId, Instant> = HashMap::new();
+
+    loop {
+        interval.tick().await;
+
+        let releasable = channel_store.lock().unwrap().releasable();
+        for (channel, held) in releasable {
+            let Some(notifier) = notifiers.notifier_for(&channel) else {
+                tracing::warn!("No notifier for channel: {channel:?}. Dropping held listings.");
+                release(&channel_store, &channel, held.len());
+                continue;
+            };
+
+            let digest_interval = notifier.digest_interval();
+            if let Some(digest_interval) = digest_interval {
+                let sent = digests_sent
+                    .entry(channel.clone())
+                    .or_insert_with(Instant::now);
+                if sent.elapsed() < digest_interval {
+                    continue;
+                }
+            }
+
+            tracing::info!("Sending {} held listings to {channel:?}", held.len());
+            let result = notifier.notify_catch_up(&held, channel.clone()).await;
+            METRICS
+                .lock()
+                .unwrap()
+                .record_notification(channel.platform(), result.is_ok());
+            match result {
+                // Every held listing counts as a notification of its subscription.
+                Ok(()) => {
+                    let mut runtime_store = runtime_store.lock().unwrap();
+                    for entry in &held {
+                        runtime_store.record_notification(entry.subscription.id);
+                    }
+                    drop(runtime_store);
+                    release(&channel_store, &channel, held.len());
+                    if digest_interval.is_some() {
+                        digests_sent.insert(channel.clone(), Instant::now());
+                    }
+                }
+                // They stay held, and are sent again on the next check. If the catch-up was split into
+                // several messages, the ones already sent are repeated, which beats losing the rest.
+                Err(e) => tracing::error!(
+                    "Failed to send catch-up message to {channel:?}, retrying later: {e}"
+                ),
+            }
+        }
+    }
+}
+
+fn release(channel_store: &Mutex<ChannelStore>, channel: &ChannelId, count: usize) {
+    if let Err(e) = channel_store.lock().unwrap().release(channel, count) {
+        tracing::error!("Failed to save released listings of {channel:?}: {e}");
+    }
+}
@@ -0,0 +1,220 @@
+//! Parsing of the dates shown by the Hungarian marketplaces.
+//!
+//! Dates on the sites are always in Budapest time, regardless of where the bot runs. They are
+//! either relative ("ma 14:30", "tegnap 09:15", "tegnapelőtt"), numeric ("2023-11-01",
+//! "2023.11.01. 14:30") or use month names ("okt. 12. 18:40", "2023. október 12.").
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+
+use crate::{
+    models::{SITE_TIMEZONE, site_time_to_utc},
+    parsers::{Field, ParseFailure},
+};
+
+/// Month names as they appear on the sites, full and abbreviated. Matched by prefix, so "okt."
+/// and "október" are both October.
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "már", "ápr", "máj", "jún", "júl", "aug", "szep", "okt", "nov", "dec",
+];
+
+/// Parses a date relative to the current time in Budapest.
+pub fn convert_date(expression: &str) -> Result<DateTime<Utc>, ParseFailure> {
+    parse_date(expression, Utc::now().with_timezone(&SITE_TIMEZONE))
+}
+
+/// Parses a date relative to `now`. The date is read as Budapest local time. A missing time of
+/// day is taken as midnight.
+pub fn parse_date(expression: &str, now: DateTime<Tz>) -> Result<DateTime<Utc>, ParseFailure> {
+    let invalid = || ParseFailure::invalid(Field::Date, Some(expression.to_string()));
+
+    let expression = expression.trim().to_lowercase();
+    let mut words: Vec<&str> = expression.split_whitespace().collect();
+    if words.is_empty() {
+        return Err(invalid());
+    }
+
+    // The time of day is always last, if present.
+    let time = match words.last().and_then(|w| parse_time(w)) {
+        Some(time) => {
+            words.pop();
+            time
+        }
+        None => NaiveTime::MIN,
+    };
+
+    let today = now.date_naive();
+    let date = match words.as_slice() {
+        ["ma"] => Some(today),
+        ["tegnap"] => today.pred_opt(),
+        ["tegnapelőtt"] => today.pred_opt().and_then(|d| d.pred_opt()),
+        // e.g. "2023-11-01"
+        [date] if date.contains('-') => NaiveDate::parse_from_str(date, "%Y-%m-%d").ok(),
+        // e.g. "2023.11.01." or "2023. 11. 01."
+        parts => parse_dotted(parts, today),
+    }
+    .ok_or_else(invalid)?;
+
+    Ok(site_time_to_utc(date.and_time(time)))
+}
+
+/// Parses "14:30" and "14:30:15".
+fn parse_time(word: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(word, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(word, "%H:%M:%S"))
+        .ok()
+}
+
+/// Parses dates where the parts are separated by dots, and the month may be a name. The year may
+/// be left out, in which case the date is assumed to be in the last year.
+fn parse_dotted(parts: &[&str], today: NaiveDate) -> Option<NaiveDate> {
+    let parts: Vec<&str> = parts
+        .iter()
+        .flat_map(|p| p.split('.'))
+        .filter(|p| !p.is_empty())
+        .collect();
+
+    let (year, month, day) = match parts.as_slice() {
+        [year, month, day] => (Some(year.parse::<i32>().ok()?), *month, *day),
+        [month, day] => (None, *month, *day),
+        _ => return None,
+    };
+    let month = parse_month(month)?;
+    let day = day.parse::<u32>().ok()?;
+
+    match year {
+        Some(year) => NaiveDate::from_ymd_opt(year, month, day),
+        // Listings are never from the future, so e.g. "dec. 30." seen in January is from the
+        // previous year.
+        None => NaiveDate::from_ymd_opt(today.year(), month, day)
+            .filter(|date| *date <= today)
+            .or_else(|| NaiveDate::from_ymd_opt(today.year() - 1, month, day)),
+    }
+}
+
+fn parse_month(month: &str) -> Option<u32> {
+    if let Ok(number) = month.parse::<u32>() {
+        return (1..=12).contains(&number).then_some(number);
+    }
+    MONTHS
+        .iter()
+        .position(|name| month.starts_with(name))
+        .map(|index| index as u32 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDateTime, TimeZone};
+
+    fn local(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn budapest(date: NaiveDateTime) -> DateTime<Tz> {
+        SITE_TIMEZONE.from_local_datetime(&date).unwrap()
+    }
+
+    /// The expected result, given in Budapest time.
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        budapest(local(year, month, day, hour, minute)).to_utc()
+    }
+
+    #[test]
+    fn test_relative_dates() {
+        let now = budapest(local(2025, 10, 15, 12, 0));
+        assert_eq!(
+            parse_date("ma 14:32", now).unwrap(),
+            at(2025, 10, 15, 14, 32)
+        );
+        assert_eq!(
+            parse_date("tegnap 09:05", now).unwrap(),
+            at(2025, 10, 14, 9, 5)
+        );
+        assert_eq!(
+            parse_date("tegnapelőtt 23:59", now).unwrap(),
+            at(2025, 10, 13, 23, 59)
+        );
+        // No time used to panic.
+        assert_eq!(parse_date("ma", now).unwrap(), at(2025, 10, 15, 0, 0));
+        assert_eq!(parse_date("Tegnap", now).unwrap(), at(2025, 10, 14, 0, 0));
+    }
+
+    #[test]
+    fn test_relative_dates_across_dst() {
+        // Clocks went back an hour at 03:00, so noon yesterday was 25 hours ago, not 24.
+        let now = budapest(local(2025, 10, 26, 12, 0));
+        let yesterday = parse_date("tegnap 12:00", now).unwrap();
+        assert_eq!(yesterday, at(2025, 10, 25, 12, 0));
+        assert_eq!(now.to_utc() - yesterday, chrono::TimeDelta::hours(25));
+
+        // And forward an hour in the spring.
+        let now = budapest(local(2025, 3, 30, 12, 0));
+        let yesterday = parse_date("tegnap 12:00", now).unwrap();
+        assert_eq!(yesterday, at(2025, 3, 29, 12, 0));
+        assert_eq!(now.to_utc() - yesterday, chrono::TimeDelta::hours(23));
+
+        // "now" being early morning UTC is already the next day in Budapest.
+        let now = Utc
+            .with_ymd_and_hms(2025, 3, 29, 23, 30, 0)
+            .unwrap()
+            .with_timezone(&SITE_TIMEZONE);
+        assert_eq!(parse_date("ma 00:10", now).unwrap(), at(2025, 3, 30, 0, 10));
+    }
+
+    #[test]
+    fn test_absolute_dates() {
+        let now = budapest(local(2025, 10, 15, 12, 0));
+        assert_eq!(
+            parse_date("2025-10-14", now).unwrap(),
+            at(2025, 10, 14, 0, 0)
+        );
+        assert_eq!(
+            parse_date("2025-10-14 18:40", now).unwrap(),
+            at(2025, 10, 14, 18, 40)
+        );
+        assert_eq!(
+            parse_date("2025.10.14. 18:40", now).unwrap(),
+            at(2025, 10, 14, 18, 40)
+        );
+        assert_eq!(
+            parse_date("2025. 10. 14.", now).unwrap(),
+            at(2025, 10, 14, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_month_names() {
+        let now = budapest(local(2025, 10, 15, 12, 0));
+        assert_eq!(
+            parse_date("okt. 12. 18:40", now).unwrap(),
+            at(2025, 10, 12, 18, 40)
+        );
+        assert_eq!(
+            parse_date("2024. március 3.", now).unwrap(),
+            at(2024, 3, 3, 0, 0)
+        );
+        // Dates in the future are from last year.
+        assert_eq!(parse_date("dec. 30.", now).unwrap(), at(2024, 12, 30, 0, 0));
+    }
+
+    #[test]
+    fn test_invalid_dates() {
+        let now = budapest(local(2025, 10, 15, 12, 0));
+        for expression in [
+            "",
+            "holnap",
+            "2025-13-01",
+            "okt. 32.",
+            "ma 25:00",
+            "valami 12.",
+        ] {
+            let failure = parse_date(expression, now).unwrap_err();
+            assert_eq!(failure.field, Field::Date);
+            assert_eq!(failure.value.as_deref(), Some(expression));
+        }
+    }
+}
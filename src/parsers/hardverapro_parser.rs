@@ -1,10 +1,78 @@
 use crate::{
-    models::{Listing, ListingType},
-    parsers::{Field, ParseFailure, ParsedPage, ScrapeMetadata},
+    models::{Condition, Listing, ListingDetails, ListingType, Price, ScrapeMetadata},
+    parsers::{Field, MarketplaceParser, ParseFailure, ParsedPage, convert_date, host_matches},
 };
-use scraper::{ElementRef, Html, Selector};
+use scraper::{CaseSensitivity, ElementRef, Html, Selector};
+use url::Url;
 
-pub fn parse_hardverapro(body: &str) -> ParsedPage {
+const BASE_URL: &str = "https://hardverapro.hu";
+
+pub struct HardverAproParser;
+
+impl MarketplaceParser for HardverAproParser {
+    fn name(&self) -> &'static str {
+        "HardverApró"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        host_matches(url, "hardverapro.hu")
+    }
+
+    fn parse_page(&self, body: &str) -> ParsedPage {
+        let mut page = parse_hardverapro(body);
+        for listing in &mut page.listings {
+            listing.url = self.canonical_listing_url(&listing.url);
+        }
+        page
+    }
+
+    /// Listing links are absolute, but may carry tracking query parameters. The listing itself
+    /// is identified by the path only.
+    fn canonical_listing_url(&self, listing_url: &str) -> String {
+        match Url::parse(BASE_URL).and_then(|base| base.join(listing_url)) {
+            Ok(mut url) => {
+                url.set_query(None);
+                url.set_fragment(None);
+                url.to_string()
+            }
+            Err(_) => listing_url.to_string(),
+        }
+    }
+
+    fn search_name(&self, url: &Url) -> Option<String> {
+        // 1. Prefer explicit query parameter
+        if let Some(value) = url
+            .query_pairs()
+            .find(|(k, v)| k == "stext" && !v.is_empty())
+            .map(|(_, v)| v.into_owned())
+        {
+            return Some(value);
+        }
+
+        // 2. Fallback to strictest category
+        let segments = url.path_segments()?;
+
+        // Collect because we need to inspect relative position
+        let parts: Vec<_> = segments.collect();
+
+        match parts.as_slice() {
+            // .../<category>/something
+            // usually: .../<category>/keres.php
+            [.., category, _] => Some(category.to_string()),
+            _ => None,
+        }
+    }
+
+    fn supports_details(&self) -> bool {
+        true
+    }
+
+    fn parse_details(&self, body: &str) -> Result<ListingDetails, ParseFailure> {
+        parse_hardverapro_details(body)
+    }
+}
+
+fn parse_hardverapro(body: &str) -> ParsedPage {
     let document = Html::parse_document(body);
 
     let mut listings: Vec<Listing> = Vec::new();
@@ -68,10 +136,7 @@ pub fn parse_hardverapro(body: &str) -> ParsedPage {
 
 /// Parses
 /// Expects an ad, which is defined by li.media
-/// Ignores listings without parsable price (keresem, ingyen)
 fn parse_hardverapro_listing(ad: ElementRef<'_>) -> Result<Listing, ParseFailure> {
-    // const BASE_URL: &str = "https://hardverapro.hu";
-
     let ribbon_sel = Selector::parse("a.uad-image>div.uad-corner-ribbon>span").unwrap();
     let listing_type = match ad.select(&ribbon_sel).next() {
         Some(ribbon) => match ribbon.inner_html().as_str() {
@@ -116,14 +181,26 @@ fn parse_hardverapro_listing(ad: ElementRef<'_>) -> Result<Listing, ParseFailure
         .replace(" ", "")
         .replace("Ft", "");
     let price = match price_str.as_str() {
-        "Ingyenes" => 0.0,
-        "Csere" => return Err(ParseFailure::skipped(Field::Price)),
-        "Keresem" => return Err(ParseFailure::skipped(Field::Price)),
+        "Ingyenes" => Price::Free,
+        "Csere" => Price::Swap,
+        "Keresem" => Price::Wanted,
         _ => price_str
             .parse::<f64>()
+            .map(Price::Fixed)
             .map_err(|_e| ParseFailure::invalid(Field::Price, Some(price_str.to_string())))?,
     };
 
+    // Image links are protocol relative. The retina image is the full sized one.
+    let img_sel = Selector::parse("a.uad-image>img").unwrap();
+    let img_url = ad
+        .select(&img_sel)
+        .next()
+        .and_then(|img| img.attr("data-retina-url").or(img.attr("src")))
+        .map(|src| match src.strip_prefix("//") {
+            Some(rest) => format!("https://{rest}"),
+            None => src.to_string(),
+        });
+
     let frozed_sel = Selector::parse("div.uad-price-iced").unwrap();
     let frozen = ad.select(&frozed_sel).next().is_some();
 
@@ -160,6 +237,13 @@ fn parse_hardverapro_listing(ad: ElementRef<'_>) -> Result<Listing, ParseFailure
         None => 0,
     };
 
+    // Business sellers are marked on the listing itself, and with an icon next to their name.
+    let business_icon_sel = Selector::parse(".user-business-icon").unwrap();
+    let seller_is_business = ad
+        .value()
+        .has_class("uad-business-user", CaseSensitivity::AsciiCaseInsensitive)
+        || ad.select(&business_icon_sel).next().is_some();
+
     let seller_url_sel = Selector::parse("span.uad-user-text>a").unwrap();
     // We need to cat the seller urls with the base url since they are relative
     let seller_url = ad
@@ -170,7 +254,6 @@ fn parse_hardverapro_listing(ad: ElementRef<'_>) -> Result<Listing, ParseFailure
         .ok_or(ParseFailure::missing(Field::SellerUrlHref))?
         .to_string();
 
-    // TODO: maybe parse the date?
     let date_sel = Selector::parse("div.uad-time>time").unwrap();
     let date_str = ad
         .select(&date_sel)
@@ -178,62 +261,134 @@ fn parse_hardverapro_listing(ad: ElementRef<'_>) -> Result<Listing, ParseFailure
         .ok_or(ParseFailure::missing(Field::Date))?
         .inner_html();
 
-    if date_str.contains("Előresorolva") {
-        return Err(ParseFailure::skipped(Field::Date));
-    }
-
-    let date = convert_date(date_str.as_str())
-        .map_err(|_e| ParseFailure::invalid(Field::Date, Some(date_str)))?;
+    // Bumped listings show "Előresorolva" instead of their date.
+    let bumped = date_str.contains("Előresorolva");
+    let date = match bumped {
+        true => None,
+        false => Some(convert_date(date_str.as_str())?),
+    };
 
     Ok(Listing {
         id,
+        img_url,
         url,
-        // img_url,
         title,
         price,
         cities,
         date,
+        bumped,
         frozen,
         seller_name,
         seller_ratings,
         seller_url,
+        seller_is_business,
         listing_type,
+        details: None,
     })
 }
 
-use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime};
-fn convert_date(expression: &str) -> Result<NaiveDateTime, chrono::ParseError> {
-    let now = Local::now().naive_local();
-
-    if expression.starts_with("ma") {
-        // e.g. "ma 14:30"
-        let time_part = expression
-            .split_whitespace()
-            .nth(1)
-            .expect("Unhandled date format: 'ma' without following hour and minute");
-        let time = NaiveTime::parse_from_str(time_part, "%H:%M")?;
-
-        Ok(NaiveDate::from_ymd_opt(now.year(), now.month(), now.day())
-            .unwrap() // Should never fail.
-            .and_time(time))
-    } else if expression.starts_with("tegnap") {
-        // e.g. "tegnap 09:15"
-        let time_part = expression
-            .split_whitespace()
-            .nth(1)
-            .expect("Unhandled date format: 'tegnap' without following hour and minute");
-        let time = NaiveTime::parse_from_str(time_part, "%H:%M")?;
-
-        Ok(NaiveDate::from_ymd_opt(now.year(), now.month(), now.day())
-            .unwrap() // Should never fail.
-            .and_time(time)
-            - Duration::days(1))
-    } else {
-        // e.g. "2023-11-01"
-        let date = NaiveDate::parse_from_str(expression, "%Y-%m-%d")?;
-        // We can't know what the time is, so we assume 00:00:00
-        Ok(date.and_hms_opt(0, 0, 0).unwrap())
+/// Parses the detail page of a listing.
+///
+/// Only the description is required, everything else is optional on the page.
+fn parse_hardverapro_details(body: &str) -> Result<ListingDetails, ParseFailure> {
+    let document = Html::parse_document(body);
+
+    let description_sel = Selector::parse("div.uad-content-block div.rtif-content").unwrap();
+    let description = document
+        .select(&description_sel)
+        .next()
+        .ok_or(ParseFailure::missing(Field::Description))?
+        .text()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    // The details table is a list of "label: value" rows.
+    let row_sel = Selector::parse("div.uad-details tr").unwrap();
+    let th_sel = Selector::parse("th").unwrap();
+    let td_sel = Selector::parse("td").unwrap();
+    let mut condition = None;
+    let mut warranty = None;
+    let mut shipping = Vec::new();
+    for row in document.select(&row_sel) {
+        let (Some(label), Some(value)) = (row.select(&th_sel).next(), row.select(&td_sel).next())
+        else {
+            continue;
+        };
+        let label = label.text().collect::<String>();
+        let value = value.text().collect::<String>().trim().to_string();
+
+        match label.trim().trim_end_matches(':') {
+            "Állapot" => {
+                condition = match value.to_lowercase().as_str() {
+                    "új" | "bontatlan" => Some(Condition::New),
+                    "használt" => Some(Condition::Used),
+                    _ => None,
+                }
+            }
+            "Garancia" => warranty = Some(value),
+            "Szállítás" => {
+                shipping = value
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            }
+            _ => {}
+        }
     }
+
+    // Full sized images are linked from the slider.
+    let image_sel = Selector::parse("div.uad-slider a[href]").unwrap();
+    let images = document
+        .select(&image_sel)
+        .filter_map(|a| a.attr("href"))
+        .map(|src| match src.strip_prefix("//") {
+            Some(rest) => format!("https://{rest}"),
+            None => src.to_string(),
+        })
+        .collect();
+
+    Ok(ListingDetails {
+        description,
+        condition,
+        warranty,
+        shipping,
+        images,
+    })
+}
+
+/// Extracts the seller's identifier from a seller profile link. Private sellers link to their
+/// profile, e.g. `/tag/kovacs_peter_8.html?uadid=4795714` => `kovacs_peter_8`, business sellers to
+/// their listings, e.g. `/aprok/hirdeto/stylebolt_hu/index.html?uadid=7303718` => `stylebolt_hu`.
+/// Works with both relative and absolute links. The identifier is lowercased.
+pub fn seller_slug(seller_url: &str) -> Option<String> {
+    let path = seller_url.split(['?', '#']).next()?;
+    let slug = if let Some((_, rest)) = path.split_once("/tag/") {
+        rest.split(['.', '/']).next()?
+    } else if let Some((_, rest)) = path.split_once("/aprok/hirdeto/") {
+        rest.split('/').next()?
+    } else {
+        return None;
+    };
+    is_seller_slug(slug).then(|| slug.to_lowercase())
+}
+
+/// Whether the text can be a seller identifier on its own: letters, digits, `_` and `-`.
+pub fn is_seller_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// The page that lists every listing of the seller. The site links business sellers to it from
+/// search results. It is expected to use the same markup as search results, so that it can be
+/// monitored like any other subscription, but there is no saved copy of it to test against yet.
+pub fn seller_listings_url(slug: &str) -> String {
+    format!("{BASE_URL}/aprok/hirdeto/{slug}/index.html")
 }
 
 #[cfg(test)]
@@ -244,7 +399,137 @@ mod tests {
     fn test_parse_hardverapro() {
         let body = include_str!("../../tests/71_dated_listings.html");
         let results = parse_hardverapro(body);
-        assert_eq!(results.listings.len(), 71);
-        assert_eq!(results.failures.len(), 29);
+        assert_eq!(results.listings.len(), 100);
+        assert_eq!(results.failures.len(), 0);
+
+        // Bumped listings and wanted posts are parsed too.
+        let bumped = results.listings.iter().filter(|l| l.bumped).count();
+        assert_eq!(bumped, 28);
+        assert!(
+            results
+                .listings
+                .iter()
+                .all(|l| l.bumped == l.date.is_none())
+        );
+        let wanted = results
+            .listings
+            .iter()
+            .filter(|l| l.price == Price::Wanted)
+            .count();
+        assert_eq!(wanted, 2);
+        let business = results
+            .listings
+            .iter()
+            .filter(|l| l.seller_is_business)
+            .count();
+        assert_eq!(business, 7);
+
+        let first = &results.listings[0];
+        assert_eq!(first.id, 4795714);
+        assert!(first.bumped);
+        assert_eq!(first.price, Price::Fixed(84990.0));
+        assert_eq!(results.listings[1].price, Price::Wanted);
+    }
+
+    #[test]
+    fn test_hardverapro_through_registry() {
+        let parser = crate::parsers::parser_for(
+            "https://hardverapro.hu/aprok/hardver/videokartya/keres.php?stext=RX+6700+XT",
+        )
+        .expect("HardverApró URLs should have a parser");
+        assert_eq!(parser.name(), "HardverApró");
+
+        let body = include_str!("../../tests/71_dated_listings.html");
+        let results = parser.parse_page(body);
+        assert_eq!(results.listings.len(), 100);
+        assert!(
+            results
+                .listings
+                .iter()
+                .all(|l| l.url.starts_with("https://hardverapro.hu/apro/") && !l.url.contains('?'))
+        );
+    }
+
+    #[test]
+    fn test_canonical_listing_url() {
+        let parser = HardverAproParser;
+        assert_eq!(
+            parser.canonical_listing_url("/apro/rx_6700_xt/friss.html?utm_source=x#top"),
+            "https://hardverapro.hu/apro/rx_6700_xt/friss.html"
+        );
+        assert_eq!(
+            parser.canonical_listing_url("https://hardverapro.hu/apro/rx_6700_xt/friss.html"),
+            "https://hardverapro.hu/apro/rx_6700_xt/friss.html"
+        );
+    }
+
+    // The fixture was written by hand after the markup of a live detail page, not saved from the
+    // site. Replace it with a saved page when one is at hand.
+    #[test]
+    fn test_parse_hardverapro_details() {
+        let body = include_str!("../../tests/hardverapro_listing.html");
+        let details = HardverAproParser.parse_details(body).unwrap();
+        assert!(
+            details
+                .description
+                .starts_with("Eladó egy Sapphire Pulse RX 6700 XT")
+        );
+        assert_eq!(details.condition, Some(Condition::Used));
+        assert_eq!(details.warranty.as_deref(), Some("2026-03-01-ig"));
+        assert_eq!(
+            details.shipping,
+            vec!["Személyes átvétel", "Foxpost", "MPL"]
+        );
+        assert_eq!(details.images.len(), 3);
+        assert_eq!(
+            details.images[0],
+            "https://cdn.rios.hu/dl/uad/2025-10/6301234/sapphire_1.jpg"
+        );
+
+        // Search result pages are not detail pages.
+        let search = include_str!("../../tests/71_dated_listings.html");
+        assert!(HardverAproParser.parse_details(search).is_err());
+    }
+
+    #[test]
+    fn test_seller_slug() {
+        assert_eq!(
+            seller_slug("/tag/kovacs_peter_8.html?uadid=4795714"),
+            Some("kovacs_peter_8".to_string())
+        );
+        assert_eq!(
+            seller_slug("https://hardverapro.hu/tag/Jeanne_d_arc.html"),
+            Some("jeanne_d_arc".to_string())
+        );
+        assert_eq!(
+            seller_slug("/aprok/hirdeto/stylebolt_hu/index.html?uadid=7303718"),
+            Some("stylebolt_hu".to_string())
+        );
+        assert_eq!(seller_slug("Kovács.Péter"), None);
+        assert_eq!(
+            seller_slug("https://hardverapro.hu/aprok/hardver/index.html"),
+            None
+        );
+        assert_eq!(seller_slug("/tag/a<b>.html"), None);
+    }
+
+    #[test]
+    fn test_business_seller_slug() {
+        let body = include_str!("../../tests/71_dated_listings.html");
+        let results = parse_hardverapro(body);
+        let listing = results
+            .listings
+            .iter()
+            .find(|l| l.seller_url.contains("/aprok/hirdeto/stylebolt_hu/"))
+            .unwrap();
+        assert!(listing.seller_is_business);
+        assert_eq!(
+            seller_slug(&listing.seller_url).as_deref(),
+            Some("stylebolt_hu")
+        );
+        assert_eq!(
+            seller_listings_url("stylebolt_hu"),
+            format!("{BASE_URL}/aprok/hirdeto/stylebolt_hu/index.html")
+        );
     }
 }
@@ -0,0 +1,285 @@
+use crate::{
+    models::{Listing, ListingType, Price, ScrapeMetadata},
+    parsers::{Field, MarketplaceParser, ParseFailure, ParsedPage, convert_date, host_matches},
+};
+use scraper::{ElementRef, Html, Selector};
+use url::Url;
+
+const BASE_URL: &str = "https://www.jofogas.hu";
+
+pub struct JofogasParser;
+
+impl MarketplaceParser for JofogasParser {
+    fn name(&self) -> &'static str {
+        "Jófogás"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        host_matches(url, "jofogas.hu")
+    }
+
+    fn parse_page(&self, body: &str) -> ParsedPage {
+        let mut page = parse_jofogas(body);
+        for listing in &mut page.listings {
+            listing.url = self.canonical_listing_url(&listing.url);
+        }
+        page
+    }
+
+    /// Listing links carry the position in the search results as a query parameter.
+    fn canonical_listing_url(&self, listing_url: &str) -> String {
+        match Url::parse(BASE_URL).and_then(|base| base.join(listing_url)) {
+            Ok(mut url) => {
+                url.set_query(None);
+                url.set_fragment(None);
+                url.to_string()
+            }
+            Err(_) => listing_url.to_string(),
+        }
+    }
+
+    fn search_name(&self, url: &Url) -> Option<String> {
+        // 1. The search text
+        if let Some(value) = url
+            .query_pairs()
+            .find(|(k, v)| k == "q" && !v.is_empty())
+            .map(|(_, v)| v.into_owned())
+        {
+            return Some(value);
+        }
+
+        // 2. The category, which is the last path segment (e.g. /magyarorszag/videokartya)
+        url.path_segments()?
+            .rfind(|s| !s.is_empty())
+            .map(|s| s.to_string())
+    }
+}
+
+fn parse_jofogas(body: &str) -> ParsedPage {
+    let document = Html::parse_document(body);
+
+    let category_sel = Selector::parse("ol.breadcrumb>li.active").unwrap();
+    let category = document
+        .select(&category_sel)
+        .next()
+        .map(|c| c.text().collect::<String>().trim().to_string());
+
+    let minprice_sel = Selector::parse("input[name=\"min_price\"]").unwrap();
+    let min_price = document
+        .select(&minprice_sel)
+        .next()
+        .and_then(|x| x.attr("value"))
+        .and_then(|price| price.parse::<f64>().ok());
+
+    let maxprice_sel = Selector::parse("input[name=\"max_price\"]").unwrap();
+    let max_price = document
+        .select(&maxprice_sel)
+        .next()
+        .and_then(|x| x.attr("value"))
+        .and_then(|price| price.parse::<f64>().ok());
+
+    let mut listings = Vec::new();
+    let mut failures = Vec::new();
+
+    let ad_sel = Selector::parse("div.list-item").unwrap();
+    for ad in document.select(&ad_sel) {
+        match parse_jofogas_listing(ad) {
+            Ok(listing) => listings.push(listing),
+            Err(e) => failures.push(e),
+        }
+    }
+
+    ParsedPage {
+        metadata: ScrapeMetadata {
+            name: None,
+            category,
+            min_price,
+            max_price,
+        },
+        listings,
+        failures,
+    }
+}
+
+/// Expects an ad, which is defined by div.list-item
+///
+/// Search result cards don't contain any information about the seller apart from whether it's a
+/// company, so the seller fields are left empty.
+fn parse_jofogas_listing(ad: ElementRef<'_>) -> Result<Listing, ParseFailure> {
+    // Featured listings are highlighted
+    let listing_type = match ad.value().has_class(
+        "highlighted",
+        scraper::CaseSensitivity::AsciiCaseInsensitive,
+    ) {
+        true => ListingType::Featured,
+        false => ListingType::Regular,
+    };
+
+    let link_sel = Selector::parse("h3.item-title>a.subject").unwrap();
+    let link = ad
+        .select(&link_sel)
+        .next()
+        .ok_or(ParseFailure::missing(Field::Url))?;
+
+    let url = link
+        .attr("href")
+        .ok_or(ParseFailure::missing(Field::UrlHref))?
+        .to_string();
+
+    // The ID is at the end of the link, e.g. ..._142233445.htm
+    let id_str = url
+        .split('?')
+        .next()
+        .and_then(|path| path.strip_suffix(".htm"))
+        .and_then(|path| path.rsplit('_').next())
+        .ok_or(ParseFailure::missing(Field::Id))?;
+    let id = id_str
+        .parse::<i64>()
+        .map_err(|_e| ParseFailure::invalid(Field::Id, Some(id_str.to_string())))?;
+
+    let title = link.text().collect::<String>().trim().to_string();
+    if title.is_empty() {
+        return Err(ParseFailure::missing(Field::Title));
+    }
+
+    let price_sel = Selector::parse("span.price-value").unwrap();
+    // Thousands are separated by non-breaking spaces.
+    let price_str: String = ad
+        .select(&price_sel)
+        .next()
+        .ok_or(ParseFailure::missing(Field::Price))?
+        .text()
+        .collect::<String>()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let price = match price_str.as_str() {
+        "Ingyenes" => Price::Free,
+        _ => price_str
+            .parse::<f64>()
+            .map(Price::Fixed)
+            .map_err(|_e| ParseFailure::invalid(Field::Price, Some(price_str.to_string())))?,
+    };
+
+    // This is a single location (e.g. "Budapest, XI. kerület"), not a list of cities.
+    let city_sel = Selector::parse("section.cityname").unwrap();
+    let city = ad
+        .select(&city_sel)
+        .next()
+        .ok_or(ParseFailure::missing(Field::Cities))?
+        .text()
+        .collect::<String>()
+        .trim()
+        .to_string();
+
+    let date_sel = Selector::parse("div.time").unwrap();
+    let date_str = ad
+        .select(&date_sel)
+        .next()
+        .ok_or(ParseFailure::missing(Field::Date))?
+        .text()
+        .collect::<String>()
+        .trim()
+        .to_string();
+    let date = convert_date(date_str.as_str())?;
+
+    let img_sel = Selector::parse("section.imageBox img").unwrap();
+    let img_url = ad
+        .select(&img_sel)
+        .next()
+        .and_then(|img| img.attr("src"))
+        .map(|src| src.to_string());
+
+    let company_sel = Selector::parse("span.badge-company").unwrap();
+    let seller_is_business = ad.select(&company_sel).next().is_some();
+
+    Ok(Listing {
+        id,
+        img_url,
+        url,
+        title,
+        price,
+        cities: vec![city],
+        date: Some(date),
+        // Jófogás doesn't show bumped listings differently.
+        bumped: false,
+        // Jófogás has no frozen listings.
+        frozen: false,
+        seller_name: String::new(),
+        seller_ratings: 0,
+        seller_url: String::new(),
+        seller_is_business,
+        listing_type,
+        details: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::parser_for;
+
+    // The fixture was written by hand after the markup of the live site, not saved from it, so
+    // the date formats in it are a guess. Replace it with a saved results page when one is at
+    // hand.
+    #[test]
+    fn test_parse_jofogas() {
+        let body = include_str!("../../tests/jofogas_search.html");
+        let parser = parser_for("https://www.jofogas.hu/magyarorszag/videokartya?q=rx+6700")
+            .expect("Jófogás URLs should have a parser");
+        assert_eq!(parser.name(), "Jófogás");
+
+        let results = parser.parse_page(body);
+        assert_eq!(results.metadata.category.as_deref(), Some("Videókártya"));
+        assert_eq!(results.metadata.min_price, Some(50000.0));
+        assert_eq!(results.metadata.max_price, Some(150000.0));
+
+        assert_eq!(results.listings.len(), 7);
+        assert!(results.failures.is_empty());
+
+        let first = &results.listings[0];
+        assert_eq!(first.id, 142233445);
+        assert_eq!(first.title, "Sapphire Pulse RX 6700 XT 12GB");
+        assert_eq!(
+            first.url,
+            "https://www.jofogas.hu/budapest/sapphire-pulse-rx-6700-xt-12gb_142233445.htm"
+        );
+        assert_eq!(first.price, Price::Fixed(109990.0));
+        assert_eq!(first.cities, vec!["Budapest, XI. kerület".to_string()]);
+        assert!(first.seller_is_business);
+        assert!(matches!(first.listing_type, ListingType::Featured));
+        assert_eq!(
+            first.img_url.as_deref(),
+            Some("https://img.jofogas.hu/620x620aspect/sapphire_pulse_rx_6700_xt_1.jpg")
+        );
+
+        let free = results.listings.iter().find(|l| l.id == 142101010).unwrap();
+        assert_eq!(free.price, Price::Free);
+        assert!(!free.seller_is_business);
+
+        let no_image = results.listings.iter().find(|l| l.id == 142000001).unwrap();
+        assert_eq!(no_image.img_url, None);
+    }
+
+    #[test]
+    fn test_unknown_date_format() {
+        let body = include_str!("../../tests/jofogas_search.html")
+            .replace("okt. 12. 18:40", "valamikor régen");
+        let results = JofogasParser.parse_page(&body);
+
+        // Only the listing with the unknown date is lost.
+        assert_eq!(results.listings.len(), 6);
+        assert_eq!(results.failures.len(), 1);
+        assert_eq!(results.failures[0].field, Field::Date);
+        assert_eq!(results.invalid_filed_listing_count(), 1);
+    }
+
+    #[test]
+    fn test_jofogas_search_name() {
+        let parser = JofogasParser;
+        let url = Url::parse("https://www.jofogas.hu/magyarorszag/videokartya?q=rx+6700").unwrap();
+        assert_eq!(parser.search_name(&url), Some("rx 6700".to_string()));
+        let url = Url::parse("https://www.jofogas.hu/magyarorszag/videokartya").unwrap();
+        assert_eq!(parser.search_name(&url), Some("videokartya".to_string()));
+    }
+}
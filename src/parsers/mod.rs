@@ -1,8 +1,69 @@
+mod date;
 mod hardverapro_parser;
+mod jofogas_parser;
 
-use crate::models::Listing;
+use url::Url;
 
-pub use self::hardverapro_parser::parse_hardverapro;
+use crate::models::{Listing, ListingDetails, ScrapeMetadata};
+
+use self::date::convert_date;
+pub use self::hardverapro_parser::{
+    HardverAproParser, is_seller_slug, seller_listings_url, seller_slug,
+};
+pub use self::jofogas_parser::JofogasParser;
+
+/// A parser for the search result pages of a single marketplace.
+///
+/// To add support for a new site, implement this trait and add the parser to [`PARSERS`].
+pub trait MarketplaceParser: Send + Sync {
+    /// Human readable name of the marketplace.
+    fn name(&self) -> &'static str;
+
+    /// Whether pages at this URL can be parsed by this parser.
+    fn matches(&self, url: &Url) -> bool;
+
+    /// Parses a search result page.
+    ///
+    /// Listing URLs in the result should already be canonical.
+    fn parse_page(&self, body: &str) -> ParsedPage;
+
+    /// Turns a (possibly relative) link to a listing into a canonical, absolute URL.
+    fn canonical_listing_url(&self, listing_url: &str) -> String;
+
+    /// A human readable name for a search, generated from its URL. Used to name subscriptions.
+    fn search_name(&self, url: &Url) -> Option<String>;
+
+    /// Whether `parse_details` is implemented. Detail pages are only fetched if it is.
+    fn supports_details(&self) -> bool {
+        false
+    }
+
+    /// Parses the detail page of a single listing.
+    ///
+    /// Sites without detail page support skip it.
+    fn parse_details(&self, _body: &str) -> Result<ListingDetails, ParseFailure> {
+        Err(ParseFailure::skipped(Field::Description))
+    }
+}
+
+/// Every supported marketplace. The first parser that matches a URL is used.
+pub static PARSERS: &[&dyn MarketplaceParser] = &[&HardverAproParser, &JofogasParser];
+
+/// Picks the parser for the given URL based on its host.
+pub fn parser_for(url: &str) -> Option<&'static dyn MarketplaceParser> {
+    let url = Url::parse(url).ok()?;
+    PARSERS.iter().copied().find(|p| p.matches(&url))
+}
+
+/// Returns true if the host of the URL is `domain` or a subdomain of it.
+fn host_matches(url: &Url, domain: &str) -> bool {
+    url.host_str().is_some_and(|host| {
+        host == domain
+            || host
+                .strip_suffix(domain)
+                .is_some_and(|sub| sub.ends_with('.'))
+    })
+}
 
 pub struct ParsedPage {
     pub metadata: ScrapeMetadata,
@@ -10,14 +71,6 @@ pub struct ParsedPage {
     pub failures: Vec<ParseFailure>,
 }
 
-#[derive(Debug, Clone)]
-pub struct ScrapeMetadata {
-    pub name: Option<String>,
-    pub category: Option<String>,
-    pub min_price: Option<f64>,
-    pub max_price: Option<f64>,
-}
-
 impl ParsedPage {
     pub fn skipped_listings_count(&self) -> usize {
         self.failures
@@ -46,7 +99,7 @@ impl ParsedPage {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Field {
     Title,
     Id,
@@ -59,6 +112,7 @@ pub enum Field {
     SellerRatings,
     SellerUrl,
     SellerUrlHref,
+    Description,
 }
 
 impl std::fmt::Display for Field {
@@ -77,17 +131,19 @@ impl std::fmt::Display for Field {
             Field::SellerUrl => write!(f, "seller link "),
             // This is a very unlikely edgecase.
             Field::SellerUrlHref => write!(f, "seller link href"),
+            Field::Description => write!(f, "description"),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ParseFailureKind {
     Missing,
     Invalid,
     Skipped,
 }
 
+#[derive(Debug, Clone)]
 pub struct ParseFailure {
     pub field: Field,
     pub kind: ParseFailureKind,
@@ -0,0 +1,156 @@
+//! What the health and readiness endpoints report: whether startup has finished, and the
+//! connection state of each integration that keeps a connection (e.g. the Discord gateway).
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Integrations reconnect by themselves, a disconnect only makes the process unhealthy if it
+/// lasts longer than this.
+const UNHEALTHY_AFTER: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Disconnected,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Connection {
+    pub state: ConnectionState,
+    /// When the state last changed.
+    pub since: DateTime<Utc>,
+    /// Why the integration is disconnected.
+    pub error: Option<String>,
+}
+
+pub struct AppStatus {
+    started_at: Instant,
+    /// Set once every saved subscription is started.
+    ready: AtomicBool,
+    connections: Mutex<BTreeMap<&'static str, Connection>>,
+}
+
+impl Default for AppStatus {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            ready: AtomicBool::new(false),
+            connections: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl AppStatus {
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    pub fn set_ready(&self) {
+        self.ready.store(true, Ordering::Relaxed);
+    }
+
+    /// Updates the state of an integration. `since` is only moved if the state changes, so
+    /// repeated reports of the same state are fine.
+    pub fn set_connection(
+        &self,
+        integration: &'static str,
+        state: ConnectionState,
+        error: Option<String>,
+    ) {
+        let mut connections = self.connections.lock().unwrap();
+        match connections.get_mut(integration) {
+            Some(connection) if connection.state == state => connection.error = error,
+            _ => {
+                if state == ConnectionState::Disconnected {
+                    tracing::warn!("{integration} disconnected: {error:?}");
+                }
+                connections.insert(
+                    integration,
+                    Connection {
+                        state,
+                        since: Utc::now(),
+                        error,
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn connections(&self) -> BTreeMap<&'static str, Connection> {
+        self.connections.lock().unwrap().clone()
+    }
+
+    /// Alive unless an integration has been disconnected for a long time.
+    pub fn is_healthy(&self) -> bool {
+        let now = Utc::now();
+        self.connections.lock().unwrap().values().all(|c| {
+            c.state != ConnectionState::Disconnected
+                || (now - c.since).to_std().unwrap_or_default() < UNHEALTHY_AFTER
+        })
+    }
+
+    /// Every subscription is started and every integration is connected.
+    pub fn is_fully_ready(&self) -> bool {
+        self.is_ready()
+            && self
+                .connections
+                .lock()
+                .unwrap()
+                .values()
+                .all(|c| c.state == ConnectionState::Connected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        let status = AppStatus::default();
+        assert!(status.is_healthy());
+        assert!(!status.is_fully_ready());
+
+        status.set_connection("discord", ConnectionState::Connecting, None);
+        status.set_ready();
+        assert!(!status.is_fully_ready());
+
+        status.set_connection("discord", ConnectionState::Connected, None);
+        assert!(status.is_fully_ready());
+
+        status.set_connection(
+            "discord",
+            ConnectionState::Disconnected,
+            Some("gateway closed".to_string()),
+        );
+        assert!(!status.is_fully_ready());
+        // Only unhealthy after a while.
+        assert!(status.is_healthy());
+        status
+            .connections
+            .lock()
+            .unwrap()
+            .get_mut("discord")
+            .unwrap()
+            .since = Utc::now() - chrono::TimeDelta::minutes(10);
+        assert!(!status.is_healthy());
+        // Reporting the same state again keeps the time.
+        status.set_connection("discord", ConnectionState::Disconnected, None);
+        assert!(!status.is_healthy());
+    }
+}
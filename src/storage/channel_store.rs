@@ -0,0 +1,209 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    models::{ChannelId, ChannelSettings, HeldListing, QuietHours, QuietMode},
+    storage::{Persistence, generate_token},
+};
+
+/// Stores settings that apply to whole channels, and the notifications held back during quiet
+/// hours.
+pub struct ChannelStore {
+    channels: HashMap<ChannelId, ChannelSettings>,
+    persistence: Arc<dyn Persistence>,
+}
+
+impl ChannelStore {
+    pub fn new(persistence: Arc<dyn Persistence>) -> anyhow::Result<Self> {
+        let channels = persistence
+            .load_channels()?
+            .into_iter()
+            .map(|c| (c.channel.clone(), c))
+            .collect();
+
+        Ok(Self {
+            channels,
+            persistence,
+        })
+    }
+
+    pub fn get(&self, channel: &ChannelId) -> Option<&ChannelSettings> {
+        self.channels.get(channel)
+    }
+
+    /// Returns the quiet mode of the channel if its quiet hours are active right now.
+    pub fn active_quiet_mode(&self, channel: &ChannelId) -> Option<QuietMode> {
+        self.channels
+            .get(channel)
+            .and_then(|c| c.quiet_hours)
+            .filter(|q| q.is_active())
+            .map(|q| q.mode)
+    }
+
+    #[tracing::instrument(name = "ChannelStore::set_quiet_hours", skip(self))]
+    pub fn set_quiet_hours(
+        &mut self,
+        channel: ChannelId,
+        quiet_hours: Option<QuietHours>,
+    ) -> anyhow::Result<()> {
+        self.channels
+            .entry(channel.clone())
+            .or_insert_with(|| ChannelSettings::new(channel))
+            .quiet_hours = quiet_hours;
+        self.save()
+    }
+
+    /// Adds a seller to the blocklist of the channel. Returns false if it was already on it.
+    #[tracing::instrument(name = "ChannelStore::block_seller", skip(self))]
+    pub fn block_seller(&mut self, channel: ChannelId, seller: String) -> anyhow::Result<bool> {
+        let settings = self
+            .channels
+            .entry(channel.clone())
+            .or_insert_with(|| ChannelSettings::new(channel));
+        if settings
+            .blocked_sellers
+            .iter()
+            .any(|s| s.to_lowercase() == seller.to_lowercase())
+        {
+            return Ok(false);
+        }
+        settings.blocked_sellers.push(seller);
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Removes a seller from the blocklist of the channel. Returns false if it wasn't on it.
+    #[tracing::instrument(name = "ChannelStore::unblock_seller", skip(self))]
+    pub fn unblock_seller(&mut self, channel: ChannelId, seller: &str) -> anyhow::Result<bool> {
+        let Some(settings) = self.channels.get_mut(&channel) else {
+            return Ok(false);
+        };
+        let before = settings.blocked_sellers.len();
+        settings
+            .blocked_sellers
+            .retain(|s| s.to_lowercase() != seller.to_lowercase());
+        if settings.blocked_sellers.len() == before {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Returns the feed token of the channel, creating one if it has none. A reset replaces the
+    /// token, so that the old URL stops working.
+    #[tracing::instrument(name = "ChannelStore::feed_token", skip(self))]
+    pub fn feed_token(&mut self, channel: ChannelId, reset: bool) -> anyhow::Result<String> {
+        let settings = self
+            .channels
+            .entry(channel.clone())
+            .or_insert_with(|| ChannelSettings::new(channel));
+        match &settings.feed_token {
+            Some(token) if !reset => Ok(token.clone()),
+            _ => {
+                let token = generate_token();
+                settings.feed_token = Some(token.clone());
+                self.save()?;
+                Ok(token)
+            }
+        }
+    }
+
+    /// Finds the channel a feed token belongs to.
+    pub fn channel_for_feed(&self, token: &str) -> Option<&ChannelId> {
+        self.channels
+            .values()
+            .find(|c| c.feed_token.as_deref() == Some(token))
+            .map(|c| &c.channel)
+    }
+
+    #[tracing::instrument(name = "ChannelStore::hold", skip(self, listing))]
+    pub fn hold(&mut self, channel: ChannelId, listing: HeldListing) -> anyhow::Result<()> {
+        self.channels
+            .entry(channel.clone())
+            .or_insert_with(|| ChannelSettings::new(channel))
+            .held
+            .push(listing);
+        self.save()
+    }
+
+    /// Returns the held listings of every channel that is no longer in quiet hours. They are kept
+    /// until `release` is called, so that they can be sent again if sending fails.
+    pub fn releasable(&self) -> Vec<(ChannelId, Vec<HeldListing>)> {
+        self.channels
+            .values()
+            .filter(|c| !c.held.is_empty())
+            .filter(|c| self.active_quiet_mode(&c.channel) != Some(QuietMode::Hold))
+            .map(|c| (c.channel.clone(), c.held.clone()))
+            .collect()
+    }
+
+    /// Removes the first `count` held listings of the channel, once they have been sent. Listings
+    /// held since `releasable` was called are kept.
+    #[tracing::instrument(name = "ChannelStore::release", skip(self))]
+    pub fn release(&mut self, channel: &ChannelId, count: usize) -> anyhow::Result<()> {
+        let Some(settings) = self.channels.get_mut(channel) else {
+            return Ok(());
+        };
+        let count = count.min(settings.held.len());
+        settings.held.drain(..count);
+        self.save()
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        self.persistence
+            .save_channels(&self.channels.values().cloned().collect::<Vec<_>>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{Listing, ListingId, ScrapeMetadata, Subscription},
+        storage::FilePersistence,
+    };
+
+    fn held(id: ListingId) -> HeldListing {
+        HeldListing {
+            subscription: Subscription::dummy(),
+            metadata: ScrapeMetadata {
+                name: None,
+                category: None,
+                min_price: None,
+                max_price: None,
+            },
+            listing: Listing {
+                id,
+                ..Listing::dummy()
+            },
+        }
+    }
+
+    #[test]
+    fn test_held_listings_survive_restart() {
+        let dir = std::env::temp_dir().join(format!("rozsdhabot-held-{}", std::process::id()));
+        let open = || ChannelStore::new(Arc::new(FilePersistence::new(&dir).unwrap())).unwrap();
+
+        let mut store = open();
+        store.hold(ChannelId::Terminal, held(1)).unwrap();
+        store.hold(ChannelId::Terminal, held(2)).unwrap();
+
+        let mut store = open();
+        let releasable = store.releasable();
+        assert_eq!(releasable.len(), 1);
+        assert_eq!(releasable[0].1.len(), 2);
+        // Not removed until released, e.g. if sending failed.
+        assert_eq!(store.releasable()[0].1.len(), 2);
+
+        // Held while the catch-up message was being sent.
+        store.hold(ChannelId::Terminal, held(3)).unwrap();
+        store.release(&ChannelId::Terminal, 2).unwrap();
+        let ids: Vec<ListingId> = open().releasable()[0]
+            .1
+            .iter()
+            .map(|h| h.listing.id)
+            .collect();
+        assert_eq!(ids, vec![3]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
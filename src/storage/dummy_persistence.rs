@@ -1,5 +1,5 @@
 use crate::{
-    models::{Subscription, SubscriptionState},
+    models::{ChannelSettings, RecentListings, Subscription, SubscriptionState},
     storage::Persistence,
 };
 
@@ -25,4 +25,24 @@ impl Persistence for DummyPersistence {
         // println!("Saved states: {states:?}");
         Ok(())
     }
+
+    fn load_recent_listings(&self) -> anyhow::Result<Vec<RecentListings>> {
+        Ok(Vec::new())
+    }
+
+    fn save_recent_listings(&self, _recent: &[RecentListings]) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn load_channels(&self) -> anyhow::Result<Vec<ChannelSettings>> {
+        Ok(Vec::new())
+    }
+
+    fn save_channels(&self, _channels: &[ChannelSettings]) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        "disabled, nothing is saved".to_string()
+    }
 }
@@ -3,10 +3,12 @@ use anyhow::Context;
 use std::{
     fs,
     path::{Path, PathBuf},
+    time::Instant,
 };
 
 use crate::{
-    models::{Subscription, SubscriptionState},
+    metrics::METRICS,
+    models::{ChannelSettings, RecentListings, Subscription, SubscriptionState},
     storage::Persistence,
 };
 
@@ -26,11 +28,34 @@ impl Persistence for FilePersistence {
     fn save_states(&self, states: &[SubscriptionState]) -> anyhow::Result<()> {
         save_json(&self.state_path, states)
     }
+
+    fn load_recent_listings(&self) -> anyhow::Result<Vec<RecentListings>> {
+        load_json(&self.recent_path)
+    }
+
+    fn save_recent_listings(&self, recent: &[RecentListings]) -> anyhow::Result<()> {
+        save_json(&self.recent_path, recent)
+    }
+
+    fn load_channels(&self) -> anyhow::Result<Vec<ChannelSettings>> {
+        load_json(&self.channels_path)
+    }
+
+    fn save_channels(&self, channels: &[ChannelSettings]) -> anyhow::Result<()> {
+        save_json(&self.channels_path, channels)
+    }
+
+    fn describe(&self) -> String {
+        format!("JSON files in {}", self.data_dir.display())
+    }
 }
 
 pub struct FilePersistence {
+    data_dir: PathBuf,
     subscriptions_path: PathBuf,
     state_path: PathBuf,
+    recent_path: PathBuf,
+    channels_path: PathBuf,
 }
 
 impl FilePersistence {
@@ -40,8 +65,11 @@ impl FilePersistence {
         fs::create_dir_all(data_dir).context("Failed to create data directory")?;
 
         Ok(Self {
+            data_dir: data_dir.to_path_buf(),
             subscriptions_path: data_dir.join("subscriptions.json"),
             state_path: data_dir.join("state.json"),
+            recent_path: data_dir.join("recent.json"),
+            channels_path: data_dir.join("channels.json"),
         })
     }
 }
@@ -73,5 +101,13 @@ fn save_json<T: serde::Serialize>(path: &Path, data: &[T]) -> anyhow::Result<()>
     let bytes =
         serde_json::to_vec(data).with_context(|| format!("Failed to serialize {:?}", path))?;
 
-    atomic_write(path, &bytes)
+    let start = Instant::now();
+    atomic_write(path, &bytes)?;
+    if let Some(file) = path.file_stem().and_then(|s| s.to_str()) {
+        METRICS
+            .lock()
+            .unwrap()
+            .record_persistence_write(file, start.elapsed());
+    }
+    Ok(())
 }
@@ -8,13 +8,17 @@ pub use file_persistence::FilePersistence;
 
 // Store implementation for the runtime state
 mod runtime_store;
-pub use runtime_store::RuntimeStateStore;
+pub use runtime_store::{RECENT_LISTINGS, RuntimeStateStore};
 
 // Store implementation for subscriptions
 mod subscription_store;
 pub use subscription_store::SubscriptionStore;
 
-use crate::models::{Subscription, SubscriptionState};
+// Store implementation for channel wide settings
+mod channel_store;
+pub use channel_store::ChannelStore;
+
+use crate::models::{ChannelSettings, RecentListings, Subscription, SubscriptionState};
 
 pub trait Persistence: Send + Sync {
     fn load_subscriptions(&self) -> anyhow::Result<Vec<Subscription>>;
@@ -22,4 +26,30 @@ pub trait Persistence: Send + Sync {
 
     fn load_states(&self) -> anyhow::Result<Vec<SubscriptionState>>;
     fn save_states(&self, states: &[SubscriptionState]) -> anyhow::Result<()>;
+
+    fn load_recent_listings(&self) -> anyhow::Result<Vec<RecentListings>>;
+    fn save_recent_listings(&self, recent: &[RecentListings]) -> anyhow::Result<()>;
+
+    fn load_channels(&self) -> anyhow::Result<Vec<ChannelSettings>>;
+    fn save_channels(&self, channels: &[ChannelSettings]) -> anyhow::Result<()>;
+
+    /// Where the data is saved, for `/status`.
+    fn describe(&self) -> String;
+}
+
+/// A random token for URLs that must not be guessable, e.g. feeds.
+pub fn generate_token() -> String {
+    hex::encode(rand::random::<[u8; 16]>())
+}
+
+/// Compares a secret given by a client to the real one in constant time, so the response time
+/// doesn't tell how much of it was right. Hashing first hides the length too.
+pub fn secrets_match(given: &str, secret: &str) -> bool {
+    use sha2::{Digest, Sha256};
+
+    Sha256::digest(given)
+        .iter()
+        .zip(Sha256::digest(secret).iter())
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0
 }
@@ -1,12 +1,35 @@
 use crate::models::ListingId;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use crate::{models::SubscriptionState, storage::Persistence};
+use chrono::Utc;
+
+use crate::{
+    models::{FoundListing, Listing, RecentListings, ScrapeStatus, SubscriptionState},
+    storage::{Persistence, generate_token},
+};
+
+/// How many of the latest listings are kept per subscription.
+pub const RECENT_LISTINGS: usize = 50;
+
+/// The metrics are saved at least this often, even if nothing else changes.
+const METRICS_SAVE_INTERVAL: Duration = Duration::from_secs(10 * 60);
 
 /// This file stores the runtime state of the bot.
+///
+/// State is created by `add` when a monitor starts. The other updates skip subscriptions that
+/// have no state, so a scrape that finishes after its subscription was deleted doesn't bring
+/// the state back.
 pub struct RuntimeStateStore {
     states: HashMap<u64, SubscriptionState>, // sub id => state
+    recent: HashMap<u64, Vec<FoundListing>>, // sub id => newest first
+    /// Only kept in memory, it's about the current run.
+    scrape_status: HashMap<u64, ScrapeStatus>,
     persistence: Arc<dyn Persistence>,
+    saved_at: Instant,
 }
 
 impl RuntimeStateStore {
@@ -16,37 +39,264 @@ impl RuntimeStateStore {
             .into_iter()
             .map(|s| (s.subscription_id, s))
             .collect();
+        let recent = persistence
+            .load_recent_listings()?
+            .into_iter()
+            .map(|r| (r.subscription_id, r.listings))
+            .collect();
 
         Ok(Self {
             states,
+            recent,
+            scrape_status: HashMap::new(),
             persistence,
+            saved_at: Instant::now(),
         })
     }
 
     pub fn get(&self, id: u64) -> Option<&SubscriptionState> {
         self.states.get(&id)
     }
+
+    /// Creates the state of the subscription if it has none. Not saved until the first update.
+    pub fn add(&mut self, id: u64) {
+        self.states
+            .entry(id)
+            .or_insert_with(|| SubscriptionState::new(id));
+    }
+
+    /// The latest listings found by the subscription, newest first.
+    pub fn recent_listings(&self, id: u64) -> &[FoundListing] {
+        self.recent.get(&id).map(Vec::as_slice).unwrap_or_default()
+    }
     #[tracing::instrument(name = "RuntimeStateStore::update_last_seen", skip(self))]
     pub fn update_last_seen(&mut self, id: u64, listing_id: ListingId) -> anyhow::Result<()> {
-        let entry = self.states.entry(id).or_insert(SubscriptionState {
-            subscription_id: id,
-            last_seen: None,
-        });
+        let Some(entry) = self.states.get_mut(&id) else {
+            return Ok(());
+        };
 
         entry.last_seen = Some(listing_id);
         tracing::trace!("Updated last seen for subscription {}", id);
-        self.persistence
-            .save_states(&self.states.values().cloned().collect::<Vec<_>>())?;
+        self.save_states()
+    }
+
+    /// Only saves if the bumped listings changed since the last call.
+    #[tracing::instrument(name = "RuntimeStateStore::update_seen_bumped", skip(self))]
+    pub fn update_seen_bumped(
+        &mut self,
+        id: u64,
+        mut bumped: Vec<ListingId>,
+    ) -> anyhow::Result<()> {
+        bumped.sort_unstable();
+        let Some(entry) = self.states.get_mut(&id) else {
+            return Ok(());
+        };
+        if entry.seen_bumped == bumped {
+            return Ok(());
+        }
 
-        Ok(())
+        entry.seen_bumped = bumped;
+        tracing::trace!("Updated seen bumped listings for subscription {}", id);
+        self.save_states()
+    }
+
+    /// Remembers newly found listings, dropping the oldest ones over `RECENT_LISTINGS`. A listing
+    /// that is found again (e.g. it was bumped) is moved to the front.
+    #[tracing::instrument(name = "RuntimeStateStore::record_found", skip(self, listings))]
+    pub fn record_found(&mut self, id: u64, listings: &[Listing]) -> anyhow::Result<()> {
+        let Some(entry) = self.states.get_mut(&id) else {
+            return Ok(());
+        };
+        if listings.is_empty() {
+            return Ok(());
+        }
+        entry.metrics.listings_found += listings.len() as u64;
+
+        let found_at = Utc::now();
+        let recent = self.recent.entry(id).or_default();
+        recent.retain(|f| !listings.iter().any(|l| l.id == f.listing.id));
+        recent.splice(
+            0..0,
+            listings.iter().map(|listing| FoundListing {
+                found_at,
+                listing: listing.clone(),
+            }),
+        );
+        recent.truncate(RECENT_LISTINGS);
+
+        // The count is saved with the next write of the state, like the other metrics.
+        self.save_recent()
+    }
+
+    fn save_recent(&self) -> anyhow::Result<()> {
+        self.persistence.save_recent_listings(
+            &self
+                .recent
+                .iter()
+                .map(|(id, listings)| RecentListings {
+                    subscription_id: *id,
+                    listings: listings.clone(),
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Returns the feed token of the subscription, creating one if it has none. A reset replaces
+    /// the token, so that the old URL stops working. Creates the state of paused subscriptions,
+    /// which have no monitor to do it.
+    #[tracing::instrument(name = "RuntimeStateStore::feed_token", skip(self))]
+    pub fn feed_token(&mut self, id: u64, reset: bool) -> anyhow::Result<String> {
+        let entry = self
+            .states
+            .entry(id)
+            .or_insert_with(|| SubscriptionState::new(id));
+        match &entry.feed_token {
+            Some(token) if !reset => Ok(token.clone()),
+            _ => {
+                let token = generate_token();
+                entry.feed_token = Some(token.clone());
+                self.save_states()?;
+                Ok(token)
+            }
+        }
+    }
+
+    pub fn scrape_status(&self, id: u64) -> Option<&ScrapeStatus> {
+        self.scrape_status.get(&id)
+    }
+
+    /// Remembers the outcome of a scrape. Scrapes are too frequent to save each one, the metrics
+    /// are saved with the next write of the state, or after `METRICS_SAVE_INTERVAL`.
+    pub fn record_scrape(&mut self, id: u64, result: Result<(), String>) {
+        let Some(state) = self.states.get_mut(&id) else {
+            return;
+        };
+        let metrics = &mut state.metrics;
+        let status = self.scrape_status.entry(id).or_default();
+        let now = Utc::now();
+        status.last_scrape = Some(now);
+        metrics.since.get_or_insert(now);
+        metrics.total_scrapes += 1;
+        match result {
+            Ok(()) => {
+                status.last_success = Some(now);
+                status.consecutive_failures = 0;
+                metrics.last_success = Some(now);
+            }
+            Err(e) => {
+                status.consecutive_failures += 1;
+                status.total_failures += 1;
+                status.last_error = Some(e.clone());
+                metrics.failed_scrapes += 1;
+                metrics.last_error = Some(e);
+                metrics.last_error_at = Some(now);
+            }
+        }
+
+        if self.saved_at.elapsed() >= METRICS_SAVE_INTERVAL
+            && let Err(e) = self.save_states()
+        {
+            tracing::error!("Failed to save metrics: {e}");
+        }
+    }
+
+    /// Counts a notification that was sent. Saved with the next write, like the scrapes.
+    pub fn record_notification(&mut self, id: u64) {
+        if let Some(state) = self.states.get_mut(&id) {
+            state.metrics.notifications_sent += 1;
+        }
+    }
+
+    /// Finds the subscription a feed token belongs to.
+    pub fn subscription_for_feed(&self, token: &str) -> Option<u64> {
+        self.states
+            .values()
+            .find(|s| s.feed_token.as_deref() == Some(token))
+            .map(|s| s.subscription_id)
     }
 
     #[tracing::instrument(name = "RuntimeStateStore::remove", skip(self))]
     pub fn remove(&mut self, id: u64) {
         self.states.remove(&id);
+        self.scrape_status.remove(&id);
+        if self.recent.remove(&id).is_some() {
+            self.save_recent().unwrap();
+        }
         tracing::trace!("Removed subscription state for subscription {}", id);
+        self.save_states().unwrap();
+    }
+
+    fn save_states(&mut self) -> anyhow::Result<()> {
+        self.saved_at = Instant::now();
         self.persistence
             .save_states(&self.states.values().cloned().collect::<Vec<_>>())
-            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::DummyPersistence;
+
+    #[test]
+    fn test_record_found() {
+        let mut store = RuntimeStateStore::new(Arc::new(DummyPersistence)).unwrap();
+        store.add(1);
+        let listing = |id| Listing {
+            id,
+            ..Listing::dummy()
+        };
+
+        store.record_found(1, &[listing(1), listing(2)]).unwrap();
+        store.record_found(1, &[listing(3), listing(1)]).unwrap();
+        let ids: Vec<ListingId> = store
+            .recent_listings(1)
+            .iter()
+            .map(|f| f.listing.id)
+            .collect();
+        assert_eq!(ids, vec![3, 1, 2]);
+
+        let many: Vec<Listing> = (10..100).map(listing).collect();
+        store.record_found(1, &many).unwrap();
+        assert_eq!(store.recent_listings(1).len(), RECENT_LISTINGS);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut store = RuntimeStateStore::new(Arc::new(DummyPersistence)).unwrap();
+        store.add(1);
+        store.record_scrape(1, Ok(()));
+        store.record_scrape(1, Err("timeout".to_string()));
+        store.record_found(1, &[Listing::dummy()]).unwrap();
+        store.record_notification(1);
+
+        let metrics = &store.get(1).unwrap().metrics;
+        assert_eq!(metrics.total_scrapes, 2);
+        assert_eq!(metrics.failed_scrapes, 1);
+        assert!(metrics.last_success.is_some());
+        assert_eq!(metrics.last_error.as_deref(), Some("timeout"));
+        assert_eq!(metrics.listings_found, 1);
+        assert_eq!(metrics.notifications_sent, 1);
+        // Less than a day counts as a whole day.
+        assert_eq!(metrics.listings_per_day(Utc::now()), Some(1.0));
+        assert_eq!(store.scrape_status(1).unwrap().consecutive_failures, 1);
+    }
+
+    #[test]
+    fn test_removed_state_stays_removed() {
+        let mut store = RuntimeStateStore::new(Arc::new(DummyPersistence)).unwrap();
+        store.add(1);
+        store.remove(1);
+
+        // A scrape that was running while the subscription was deleted.
+        store.record_found(1, &[Listing::dummy()]).unwrap();
+        store.update_last_seen(1, 1).unwrap();
+        store.update_seen_bumped(1, vec![1]).unwrap();
+        store.record_scrape(1, Ok(()));
+        store.record_notification(1);
+
+        assert!(store.get(1).is_none());
+        assert!(store.recent_listings(1).is_empty());
+        assert!(store.scrape_status(1).is_none());
     }
 }
@@ -4,6 +4,7 @@ use url::Url;
 
 use crate::{
     models::{ChannelId, OwnerId, Subscription, SubscriptionConfig},
+    parsers::parser_for,
     storage::Persistence,
 };
 
@@ -35,34 +36,10 @@ impl SubscriptionStore {
         }
     }
 
+    /// The parser of the site knows how to name its searches.
     fn get_name_from_url(url: &str) -> Option<String> {
-        let url = Url::parse(url).ok()?;
-
-        // 1. Prefer explicit query parameter
-        if let Some(value) = url
-            .query_pairs()
-            .find(|(k, v)| k == "stext" && !v.is_empty())
-            .map(|(_, v)| v.into_owned())
-        {
-            return Some(value);
-        }
-
-        // 2. Fallback to strictest category
-        Self::category_from_path(&url)
-    }
-
-    fn category_from_path(url: &Url) -> Option<String> {
-        let segments = url.path_segments()?;
-
-        // Collect because we need to inspect relative position
-        let parts: Vec<_> = segments.collect();
-
-        match parts.as_slice() {
-            // .../<category>/something
-            // usually: .../<category>/keres.php
-            [.., category, _] => Some(category.to_string()),
-            _ => None,
-        }
+        let parser = parser_for(url)?;
+        parser.search_name(&Url::parse(url).ok()?)
     }
 
     pub fn add_subscription(&mut self, url: String, channel: ChannelId, owner: OwnerId) -> u64 {
@@ -78,7 +55,7 @@ impl SubscriptionStore {
             // Maybe in the future.
             // platform_config: ChannelConfig::Telegram { thread_id: None },
             // metrics: SubscriptionMetrics::new(),
-            created_at: chrono::Local::now().naive_local(),
+            created_at: chrono::Utc::now(),
         };
         self.subscriptions.insert(subscription.id, subscription);
         self.next_id += 1;
@@ -112,33 +89,57 @@ impl SubscriptionStore {
         removed
     }
 
-    /// Same as remove_subscription(), but checks if the subscription we want to remove actually
-    /// belongs to the channel in question
-    pub fn remove_subscription_channel(&mut self, id: u64, channel: ChannelId) -> bool {
-        if let Some(sub) = self.subscriptions.get(&id)
-            && sub.channels.contains(&channel)
+    /// Applies `update` to the subscription if it exists and belongs to the given channel, then
+    /// saves. Returns the updated subscription, so that its monitor can be restarted.
+    pub fn update_subscription_channel(
+        &mut self,
+        id: u64,
+        channel: &ChannelId,
+        update: impl FnOnce(&mut Subscription),
+    ) -> Option<Subscription> {
+        if !self
+            .subscriptions
+            .get(&id)
+            .is_some_and(|s| s.channels.contains(channel))
         {
-            // Use the return value from remove subscription
-            return self.remove_subscription(id);
+            return None;
         }
-        // Or just return false, since the subscription doesn't exist.
-        false
+        self.update_subscription(id, update)
     }
 
-    // pub fn update_subscription(&mut self, id: u64, subscription: Subscription) {}
+    /// Same as update_subscription_channel(), but for any channel. Only for the API, where the
+    /// caller is trusted with every subscription.
+    pub fn update_subscription(
+        &mut self,
+        id: u64,
+        update: impl FnOnce(&mut Subscription),
+    ) -> Option<Subscription> {
+        let sub = self.subscriptions.get_mut(&id)?;
+        update(sub);
+        let updated = sub.clone();
+
+        if let Err(e) = self
+            .persistence
+            .save_subscriptions(&self.subscriptions.values().cloned().collect::<Vec<_>>())
+        {
+            tracing::error!("Failed to save subscriptions when updating: {e}");
+        }
+
+        Some(updated)
+    }
 
     pub fn get_subscription(&self, id: u64) -> Option<&Subscription> {
         self.subscriptions.get(&id)
     }
 
     /// Returns a list of references to all subscriptions in a channel.
-    pub fn list_by_channel(&self, channel: ChannelId) -> Vec<&Subscription> {
+    pub fn list_by_channel(&self, channel: &ChannelId) -> Vec<&Subscription> {
         // PERF: This is a linear search through subscriptions.
         // We could implement a hasmap for this too that is managed alongside the subscriptions,
         // but it's fine for now.
         self.subscriptions
             .values()
-            .filter(|s| s.channels.contains(&channel))
+            .filter(|s| s.channels.contains(channel))
             .collect()
     }
 }
@@ -185,7 +186,7 @@ mod tests {
 
         // We can retrieve it with by the channel id.
         assert_eq!(
-            store.list_by_channel(ChannelId::Telegram {
+            store.list_by_channel(&ChannelId::Telegram {
                 chat_id: teloxide::types::ChatId(1)
             }),
             vec![&subscription]
@@ -0,0 +1,425 @@
+//! A JSON API for managing subscriptions from other programs, served by the HTTP server.
+//!
+//! Every request needs an `Authorization: Bearer TOKEN` header with one of the configured
+//! tokens. A token gives access to every subscription, not just the ones created through the API.
+//!
+//! - `GET /api/subscriptions`
+//! - `POST /api/subscriptions` with `{"url": ..., "name"?, "channels"?, "config"?}`
+//! - `GET|PATCH|DELETE /api/subscriptions/{id}`, `PATCH` takes `{"name"?, "channels"?, "config"?}`
+//! - `POST /api/subscriptions/{id}/pause` and `/resume`
+//! - `GET /api/subscriptions/{id}/state` and `/listings`
+//!
+//! Channels are written the way they are saved, e.g. `"Api"` or `{"Telegram": {"chat_id": 1}}`.
+//! Subscriptions created without channels only go to `ChannelId::Api`.
+
+use std::sync::Arc;
+
+use axum::{
+    Json, Router,
+    extract::{Path, Request, State},
+    http::{StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    AppCtx,
+    models::{
+        ChannelId, FoundListing, ListingId, OwnerId, ScrapeStatus, Subscription,
+        SubscriptionConfig, SubscriptionMetrics,
+    },
+    parsers::{PARSERS, parser_for},
+    storage::secrets_match,
+};
+
+/// Errors are returned as `{"error": "..."}`.
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+fn not_found(id: u64) -> ApiError {
+    ApiError(
+        StatusCode::NOT_FOUND,
+        format!("Subscription {id} doesn't exist"),
+    )
+}
+
+#[derive(Deserialize)]
+struct NewSubscription {
+    url: String,
+    name: Option<String>,
+    #[serde(default)]
+    channels: Vec<ChannelId>,
+    config: Option<SubscriptionConfig>,
+}
+
+/// Fields that are left out are not changed.
+#[derive(Deserialize)]
+struct SubscriptionUpdate {
+    name: Option<String>,
+    channels: Option<Vec<ChannelId>>,
+    config: Option<SubscriptionConfig>,
+}
+
+#[derive(Serialize)]
+struct RuntimeState {
+    subscription_id: u64,
+    running: bool,
+    paused: bool,
+    last_seen: Option<ListingId>,
+    seen_bumped: Vec<ListingId>,
+    recent_listings: usize,
+    /// Missing until the first scrape since startup.
+    scrape: Option<ScrapeStatus>,
+    metrics: SubscriptionMetrics,
+}
+
+pub fn routes(tokens: Vec<String>) -> Router<AppCtx> {
+    Router::new()
+        .route(
+            "/api/subscriptions",
+            get(list_subscriptions).post(create_subscription),
+        )
+        .route(
+            "/api/subscriptions/{id}",
+            get(get_subscription)
+                .patch(update_subscription)
+                .delete(delete_subscription),
+        )
+        .route("/api/subscriptions/{id}/pause", post(pause_subscription))
+        .route("/api/subscriptions/{id}/resume", post(resume_subscription))
+        .route("/api/subscriptions/{id}/state", get(subscription_state))
+        .route("/api/subscriptions/{id}/listings", get(recent_listings))
+        .route_layer(middleware::from_fn_with_state(Arc::new(tokens), authorize))
+}
+
+async fn authorize(
+    State(tokens): State<Arc<Vec<String>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| tokens.iter().any(|t| secrets_match(token, t)));
+
+    match authorized {
+        true => next.run(request).await,
+        false => {
+            ApiError(StatusCode::UNAUTHORIZED, "Invalid API token".to_string()).into_response()
+        }
+    }
+}
+
+async fn list_subscriptions(State(context): State<AppCtx>) -> Json<Vec<Subscription>> {
+    let mut subscriptions: Vec<Subscription> = context
+        .subscription_store
+        .lock()
+        .unwrap()
+        .subscriptions
+        .values()
+        .cloned()
+        .collect();
+    subscriptions.sort_by_key(|s| s.id);
+    Json(subscriptions)
+}
+
+async fn create_subscription(
+    State(context): State<AppCtx>,
+    Json(new): Json<NewSubscription>,
+) -> Result<(StatusCode, Json<Subscription>), ApiError> {
+    if parser_for(&new.url).is_none() {
+        let supported = PARSERS
+            .iter()
+            .map(|p| p.name())
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(ApiError(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("This site is not supported. Supported sites: {supported}"),
+        ));
+    }
+
+    let id = context.subscription_store.lock().unwrap().add_subscription(
+        new.url,
+        ChannelId::Api,
+        OwnerId::Api,
+    );
+    // Starts the monitor too.
+    let subscription = context
+        .update_subscription(id, |sub| {
+            if !new.channels.is_empty() {
+                sub.channels = new.channels;
+            }
+            if new.name.is_some() {
+                sub.name = new.name;
+            }
+            if let Some(config) = new.config {
+                sub.config = config;
+            }
+        })
+        .unwrap();
+    tracing::info!(
+        "New subscription added through the API with ID: {}",
+        subscription.id
+    );
+    Ok((StatusCode::CREATED, Json(subscription)))
+}
+
+async fn get_subscription(
+    State(context): State<AppCtx>,
+    Path(id): Path<u64>,
+) -> Result<Json<Subscription>, ApiError> {
+    context
+        .subscription_store
+        .lock()
+        .unwrap()
+        .get_subscription(id)
+        .cloned()
+        .map(Json)
+        .ok_or_else(|| not_found(id))
+}
+
+async fn update_subscription(
+    State(context): State<AppCtx>,
+    Path(id): Path<u64>,
+    Json(update): Json<SubscriptionUpdate>,
+) -> Result<Json<Subscription>, ApiError> {
+    // A subscription without channels can't be found from any chat.
+    if update.channels.as_ref().is_some_and(|c| c.is_empty()) {
+        return Err(ApiError(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "A subscription needs at least one channel".to_string(),
+        ));
+    }
+
+    let subscription = context
+        .update_subscription(id, |sub| {
+            if let Some(channels) = update.channels {
+                sub.channels = channels;
+            }
+            if update.name.is_some() {
+                sub.name = update.name;
+            }
+            if let Some(config) = update.config {
+                sub.config = config;
+            }
+        })
+        .ok_or_else(|| not_found(id))?;
+    Ok(Json(subscription))
+}
+
+async fn delete_subscription(
+    State(context): State<AppCtx>,
+    Path(id): Path<u64>,
+) -> Result<StatusCode, ApiError> {
+    match context.delete_subscription(id) {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(not_found(id)),
+    }
+}
+
+async fn set_paused(
+    context: AppCtx,
+    id: u64,
+    paused: bool,
+) -> Result<Json<Subscription>, ApiError> {
+    context
+        .update_subscription(id, |sub| sub.config.paused = paused)
+        .map(Json)
+        .ok_or_else(|| not_found(id))
+}
+
+async fn pause_subscription(
+    State(context): State<AppCtx>,
+    Path(id): Path<u64>,
+) -> Result<Json<Subscription>, ApiError> {
+    set_paused(context, id, true).await
+}
+
+async fn resume_subscription(
+    State(context): State<AppCtx>,
+    Path(id): Path<u64>,
+) -> Result<Json<Subscription>, ApiError> {
+    set_paused(context, id, false).await
+}
+
+async fn subscription_state(
+    State(context): State<AppCtx>,
+    Path(id): Path<u64>,
+) -> Result<Json<RuntimeState>, ApiError> {
+    let paused = context
+        .subscription_store
+        .lock()
+        .unwrap()
+        .get_subscription(id)
+        .map(|s| s.config.paused)
+        .ok_or_else(|| not_found(id))?;
+    let running = context.monitor_manager.lock().unwrap().is_running(id);
+    let (state, scrape, recent_listings) = {
+        let runtime_store = context.runtime_store.lock().unwrap();
+        (
+            runtime_store.get(id).cloned(),
+            runtime_store.scrape_status(id).cloned(),
+            runtime_store.recent_listings(id).len(),
+        )
+    };
+
+    Ok(Json(RuntimeState {
+        subscription_id: id,
+        running,
+        paused,
+        last_seen: state.as_ref().and_then(|s| s.last_seen),
+        seen_bumped: state
+            .as_ref()
+            .map(|s| s.seen_bumped.clone())
+            .unwrap_or_default(),
+        recent_listings,
+        scrape,
+        metrics: state.map(|s| s.metrics).unwrap_or_default(),
+    }))
+}
+
+async fn recent_listings(
+    State(context): State<AppCtx>,
+    Path(id): Path<u64>,
+) -> Result<Json<Vec<FoundListing>>, ApiError> {
+    if context
+        .subscription_store
+        .lock()
+        .unwrap()
+        .get_subscription(id)
+        .is_none()
+    {
+        return Err(not_found(id));
+    }
+
+    Ok(Json(
+        context
+            .runtime_store
+            .lock()
+            .unwrap()
+            .recent_listings(id)
+            .to_vec(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::Listing, web::router};
+
+    const URL: &str = "https://hardverapro.hu/aprok/hardver/videokartya/index.html";
+
+    #[tokio::test]
+    async fn test_subscription_lifecycle() {
+        let context = AppCtx::default();
+        context.status.set_ready();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/api", listener.local_addr().unwrap());
+        let router = router(context.clone(), None, Some(vec!["secret".into()]));
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        // The rest of the server doesn't need a token.
+        let response = reqwest::get(format!("{base}/../healthz")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let client = reqwest::Client::new();
+        let response = client
+            .get(format!("{base}/subscriptions"))
+            .bearer_auth("wrong")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // Created paused, so that nothing is scraped.
+        let mut config = SubscriptionConfig::default();
+        config.paused = true;
+        let response = client
+            .post(format!("{base}/subscriptions"))
+            .bearer_auth("secret")
+            .json(&json!({ "url": URL, "name": "gpu", "config": config }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let created: serde_json::Value = response.json().await.unwrap();
+        let id = created["id"].as_u64().unwrap();
+        assert_eq!(created["name"], "gpu");
+        assert_eq!(created["channels"], json!(["Api"]));
+        assert_eq!(created["owner"], json!("Api"));
+
+        let response = client
+            .post(format!("{base}/subscriptions"))
+            .bearer_auth("secret")
+            .json(&json!({ "url": "https://example.org" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        {
+            // As if it had been scraped before it was paused.
+            let mut runtime_store = context.runtime_store.lock().unwrap();
+            runtime_store.add(id);
+            runtime_store.record_found(id, &[Listing::dummy()]).unwrap();
+        }
+        let state: serde_json::Value = client
+            .get(format!("{base}/subscriptions/{id}/state"))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(state["paused"], true);
+        assert_eq!(state["running"], false);
+        assert_eq!(state["recent_listings"], 1);
+
+        let listings: serde_json::Value = client
+            .get(format!("{base}/subscriptions/{id}/listings"))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(listings[0]["listing"]["id"], Listing::dummy().id);
+
+        let response = client
+            .patch(format!("{base}/subscriptions/{id}"))
+            .bearer_auth("secret")
+            .json(&json!({ "channels": [] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let response = client
+            .delete(format!("{base}/subscriptions/{id}"))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = client
+            .get(format!("{base}/subscriptions/{id}"))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
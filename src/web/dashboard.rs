@@ -0,0 +1,650 @@
+//! A small server-rendered dashboard for managing many subscriptions at once.
+//!
+//! Logging in with the configured password sets a session cookie. Sessions are kept in memory, a
+//! restart logs everyone out, and they expire after a while. The cookie is `SameSite=Strict`, so
+//! other sites can't submit the forms in the name of a logged in user. Logins are handled one at a
+//! time and failures are answered slowly, to make guessing the password impractical.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Extension, Form, Router,
+    extract::{Path, Request, State},
+    http::{HeaderMap, StatusCode, header},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+};
+use serde::Deserialize;
+
+use crate::{
+    AppCtx,
+    config::parse_channel,
+    integrations::escape_html,
+    models::{ChannelId, OwnerId, Subscription, format_timestamp},
+    parsers::{PARSERS, parser_for},
+    storage::{generate_token, secrets_match},
+};
+
+const SESSION_COOKIE: &str = "rozsdhabot_session";
+
+/// Sessions are valid for this long after logging in, a stolen cookie stops working after that.
+const SESSION_LIFETIME: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// A wrong password is answered after this long. Logins are handled one at a time, so this is
+/// also the limit on how fast passwords can be guessed.
+const FAILED_LOGIN_DELAY: Duration = Duration::from_secs(1);
+
+/// Monitors are not started for shorter intervals from the dashboard, to go easy on the sites.
+const MIN_INTERVAL: u64 = 10;
+
+pub struct Dashboard {
+    password: String,
+    /// The expiry of each session, by token.
+    sessions: Mutex<HashMap<String, Instant>>,
+    /// Held while checking a password.
+    login: tokio::sync::Mutex<()>,
+    /// Whether the cookie is only sent over HTTPS.
+    secure: bool,
+}
+
+impl Dashboard {
+    fn new(password: String, secure: bool) -> Self {
+        Self {
+            password,
+            sessions: Mutex::new(HashMap::new()),
+            login: tokio::sync::Mutex::new(()),
+            secure,
+        }
+    }
+
+    fn session(&self, headers: &HeaderMap) -> Option<String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = Instant::now();
+        sessions.retain(|_, expiry| *expiry > now);
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .find(|(name, token)| *name == SESSION_COOKIE && sessions.contains_key(*token))
+            .map(|(_, token)| token.to_string())
+    }
+
+    /// Starts a session, returning its cookie.
+    fn start_session(&self) -> String {
+        let token = generate_token();
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(token.clone(), Instant::now() + SESSION_LIFETIME);
+        format!(
+            "{SESSION_COOKIE}={token}; HttpOnly; SameSite=Strict; Path=/dashboard; Max-Age={}{}",
+            SESSION_LIFETIME.as_secs(),
+            match self.secure {
+                true => "; Secure",
+                false => "",
+            }
+        )
+    }
+}
+
+pub fn routes(password: String, secure: bool) -> Router<AppCtx> {
+    let dashboard = Arc::new(Dashboard::new(password, secure));
+
+    let protected = Router::new()
+        .route("/dashboard", get(index))
+        .route("/dashboard/subscriptions", post(add_subscription))
+        .route(
+            "/dashboard/subscriptions/{id}",
+            get(details).post(edit_subscription),
+        )
+        .route("/dashboard/subscriptions/{id}/pause", post(pause))
+        .route("/dashboard/subscriptions/{id}/resume", post(resume))
+        .route("/dashboard/subscriptions/{id}/delete", post(delete))
+        .route("/dashboard/logout", post(logout))
+        .route_layer(middleware::from_fn(require_login));
+
+    Router::new()
+        .merge(protected)
+        .route("/dashboard/login", get(login_page).post(login))
+        .layer(Extension(dashboard))
+}
+
+async fn require_login(
+    Extension(dashboard): Extension<Arc<Dashboard>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match dashboard.session(request.headers()) {
+        Some(_) => next.run(request).await,
+        None => Redirect::to("/dashboard/login").into_response(),
+    }
+}
+
+fn page(title: &str, body: &str) -> Html<String> {
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title} - rozsdhabot</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em auto; max-width: 72em; padding: 0 1em; }}
+table {{ border-collapse: collapse; width: 100%; }}
+th, td {{ border-bottom: 1px solid #ddd; padding: .4em; text-align: left; vertical-align: top; }}
+form.inline {{ display: inline; }}
+.error {{ color: #b00; }}
+.muted {{ color: #777; }}
+.listings {{ display: grid; grid-template-columns: repeat(auto-fill, minmax(14em, 1fr)); gap: 1em; }}
+.listings img {{ max-width: 100%; max-height: 10em; }}
+</style>
+</head>
+<body>
+<h1><a href="/dashboard">rozsdhabot</a></h1>
+{body}
+</body>
+</html>
+"#,
+        title = escape_html(title),
+    ))
+}
+
+fn error_page(status: StatusCode, message: &str) -> Response {
+    (
+        status,
+        page(
+            "Error",
+            &format!(
+                r#"<p class="error">{}</p><p><a href="javascript:history.back()">Back</a></p>"#,
+                escape_html(message)
+            ),
+        ),
+    )
+        .into_response()
+}
+
+fn not_found(id: u64) -> Response {
+    error_page(
+        StatusCode::NOT_FOUND,
+        &format!("Subscription {id} doesn't exist"),
+    )
+}
+
+fn optional_timestamp(timestamp: Option<&chrono::DateTime<chrono::Utc>>) -> String {
+    timestamp.map(format_timestamp).unwrap_or("-".to_string())
+}
+
+// ==== Login ====
+
+#[derive(Deserialize)]
+struct LoginForm {
+    password: String,
+}
+
+async fn login_page() -> Html<String> {
+    page(
+        "Login",
+        r#"<form method="post" action="/dashboard/login">
+<input type="password" name="password" placeholder="Password" autofocus>
+<button>Log in</button>
+</form>"#,
+    )
+}
+
+async fn login(
+    Extension(dashboard): Extension<Arc<Dashboard>>,
+    Form(form): Form<LoginForm>,
+) -> Response {
+    let _login = dashboard.login.lock().await;
+    if !secrets_match(&form.password, &dashboard.password) {
+        tracing::warn!("Failed dashboard login attempt");
+        tokio::time::sleep(FAILED_LOGIN_DELAY).await;
+        return error_page(StatusCode::UNAUTHORIZED, "Wrong password");
+    }
+
+    (
+        [(header::SET_COOKIE, dashboard.start_session())],
+        Redirect::to("/dashboard"),
+    )
+        .into_response()
+}
+
+async fn logout(Extension(dashboard): Extension<Arc<Dashboard>>, headers: HeaderMap) -> Redirect {
+    if let Some(token) = dashboard.session(&headers) {
+        dashboard.sessions.lock().unwrap().remove(&token);
+    }
+    Redirect::to("/dashboard/login")
+}
+
+// ==== Pages ====
+
+async fn index(State(context): State<AppCtx>) -> Html<String> {
+    let mut subscriptions: Vec<Subscription> = context
+        .subscription_store
+        .lock()
+        .unwrap()
+        .subscriptions
+        .values()
+        .cloned()
+        .collect();
+    subscriptions.sort_by_key(|s| s.id);
+
+    let mut rows = String::new();
+    for sub in &subscriptions {
+        let running = context.monitor_manager.lock().unwrap().is_running(sub.id);
+        let (status, last_seen) = {
+            let runtime_store = context.runtime_store.lock().unwrap();
+            (
+                runtime_store.scrape_status(sub.id).cloned(),
+                runtime_store.get(sub.id).and_then(|s| s.last_seen),
+            )
+        };
+
+        let state = match (sub.config.paused, running) {
+            (true, _) => "paused",
+            (false, true) => "running",
+            (false, false) => "stopped",
+        };
+        let toggle = match sub.config.paused {
+            true => "resume",
+            false => "pause",
+        };
+        let failures = match &status {
+            Some(status) if status.total_failures > 0 => format!(
+                r#"<span class="error" title="{}">{} in a row, {} total</span>"#,
+                escape_html(status.last_error.as_deref().unwrap_or("")),
+                status.consecutive_failures,
+                status.total_failures
+            ),
+            _ => "0".to_string(),
+        };
+
+        rows += &format!(
+            r#"<tr>
+<td>{id}</td>
+<td><a href="/dashboard/subscriptions/{id}">{name}</a></td>
+<td>{state}</td>
+<td>{last_scrape}</td>
+<td>{failures}</td>
+<td>{last_seen}</td>
+<td>
+<form class="inline" method="post" action="/dashboard/subscriptions/{id}/{toggle}"><button>{toggle}</button></form>
+<form class="inline" method="post" action="/dashboard/subscriptions/{id}/delete" onsubmit="return confirm('Delete subscription {id}?')"><button>delete</button></form>
+</td>
+</tr>
+"#,
+            id = sub.id,
+            name = escape_html(sub.name.as_deref().unwrap_or(&sub.url)),
+            last_scrape = optional_timestamp(status.as_ref().and_then(|s| s.last_scrape.as_ref())),
+            last_seen = last_seen
+                .map(|id| id.to_string())
+                .unwrap_or("-".to_string()),
+        );
+    }
+
+    page(
+        "Subscriptions",
+        &format!(
+            r#"<form method="post" action="/dashboard/subscriptions">
+<input type="url" name="url" placeholder="Search URL" size="60" required>
+<button>Add</button>
+</form>
+<form class="inline" method="post" action="/dashboard/logout"><button>Log out</button></form>
+<h2>Subscriptions ({count})</h2>
+<table>
+<tr><th>ID</th><th>Name</th><th>Status</th><th>Last scrape</th><th>Failures</th><th>Last seen</th><th></th></tr>
+{rows}</table>"#,
+            count = subscriptions.len(),
+        ),
+    )
+}
+
+async fn details(State(context): State<AppCtx>, Path(id): Path<u64>) -> Response {
+    let Some(sub) = context
+        .subscription_store
+        .lock()
+        .unwrap()
+        .get_subscription(id)
+        .cloned()
+    else {
+        return not_found(id);
+    };
+    let recent = context
+        .runtime_store
+        .lock()
+        .unwrap()
+        .recent_listings(id)
+        .to_vec();
+
+    let checkbox = |name: &str, label: &str, checked: bool| {
+        format!(
+            r#"<label><input type="checkbox" name="{name}"{}> {label}</label><br>"#,
+            if checked { " checked" } else { "" }
+        )
+    };
+    let config = &sub.config;
+    let channels = sub
+        .channels
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut listings = String::new();
+    for found in &recent {
+        let listing = &found.listing;
+        let image = match &listing.img_url {
+            Some(url) => format!(
+                r#"<img src="{}" alt="" loading="lazy"><br>"#,
+                escape_html(url)
+            ),
+            None => String::new(),
+        };
+        listings += &format!(
+            r#"<div>{image}<a href="{url}">{title}</a><br><b>{price}</b> {cities}<br><span class="muted">found {found_at}</span></div>
+"#,
+            url = escape_html(&listing.url),
+            title = escape_html(&listing.title),
+            price = escape_html(&listing.price.to_string()),
+            cities = escape_html(&listing.cities.join(", ")),
+            found_at = format_timestamp(&found.found_at),
+        );
+    }
+    if listings.is_empty() {
+        listings = r#"<p class="muted">Nothing found yet.</p>"#.to_string();
+    }
+
+    page(
+        sub.name.as_deref().unwrap_or("Subscription"),
+        &format!(
+            r#"<h2>{id}: {name}</h2>
+<p><a href="{url}">{url}</a><br>Filters: {filter}</p>
+<form method="post" action="/dashboard/subscriptions/{id}">
+<label>Name <input name="name" value="{name}"></label><br>
+<label>Interval (seconds) <input type="number" name="interval" min="{MIN_INTERVAL}" value="{interval}"></label><br>
+<label>Channels, one per line as platform:id<br><textarea name="channels" rows="3" cols="40">{channels}</textarea></label><br>
+{featured}{regular}{bazar}{swaps}{wanted}{bumped}{enrich}
+<button>Save</button>
+</form>
+<h2>Recent listings ({count})</h2>
+<div class="listings">
+{listings}</div>"#,
+            name = escape_html(sub.name.as_deref().unwrap_or("")),
+            url = escape_html(&sub.url),
+            filter = escape_html(&config.filter.to_string()),
+            interval = config.interval,
+            channels = escape_html(&channels),
+            featured = checkbox("show_featured", "Featured listings", config.show_featured),
+            regular = checkbox("show_regular", "Regular listings", config.show_regular),
+            bazar = checkbox("show_bazar", "Bazár listings", config.show_bazar),
+            swaps = checkbox("show_swaps", "Swaps", config.show_swaps),
+            wanted = checkbox("show_wanted", "Wanted posts", config.show_wanted),
+            bumped = checkbox("show_bumped", "Bumped listings", config.show_bumped),
+            enrich = checkbox("enrich", "Fetch details", config.enrich),
+            count = recent.len(),
+        ),
+    )
+    .into_response()
+}
+
+// ==== Actions ====
+
+#[derive(Deserialize)]
+struct AddForm {
+    url: String,
+}
+
+async fn add_subscription(State(context): State<AppCtx>, Form(form): Form<AddForm>) -> Response {
+    let url = form.url.trim().to_string();
+    if parser_for(&url).is_none() {
+        let supported = PARSERS
+            .iter()
+            .map(|p| p.name())
+            .collect::<Vec<_>>()
+            .join(", ");
+        return error_page(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            &format!("This site is not supported. Supported sites: {supported}"),
+        );
+    }
+
+    let subscription = {
+        let mut subscription_store = context.subscription_store.lock().unwrap();
+        let id = subscription_store.add_subscription(url, ChannelId::Api, OwnerId::Api);
+        subscription_store.get_subscription(id).unwrap().clone()
+    };
+    let id = subscription.id;
+    context.restart_monitor(subscription);
+    tracing::info!("New subscription added from the dashboard with ID: {id}");
+
+    Redirect::to(&format!("/dashboard/subscriptions/{id}")).into_response()
+}
+
+/// Unchecked checkboxes are left out of the form.
+#[derive(Deserialize)]
+struct EditForm {
+    name: String,
+    interval: u64,
+    channels: String,
+    show_featured: Option<String>,
+    show_regular: Option<String>,
+    show_bazar: Option<String>,
+    show_swaps: Option<String>,
+    show_wanted: Option<String>,
+    show_bumped: Option<String>,
+    enrich: Option<String>,
+}
+
+async fn edit_subscription(
+    State(context): State<AppCtx>,
+    Path(id): Path<u64>,
+    Form(form): Form<EditForm>,
+) -> Response {
+    if form.interval < MIN_INTERVAL {
+        return error_page(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            &format!("The interval must be at least {MIN_INTERVAL} seconds"),
+        );
+    }
+    let channels = match form
+        .channels
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(parse_channel)
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(channels) if channels.is_empty() => {
+            return error_page(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "A subscription needs at least one channel",
+            );
+        }
+        Ok(channels) => channels,
+        Err(e) => return error_page(StatusCode::UNPROCESSABLE_ENTITY, &e),
+    };
+
+    let updated = context.update_subscription(id, |sub| {
+        let name = form.name.trim();
+        sub.name = (!name.is_empty()).then(|| name.to_string());
+        sub.channels = channels;
+        let config = &mut sub.config;
+        config.interval = form.interval;
+        config.show_featured = form.show_featured.is_some();
+        config.show_regular = form.show_regular.is_some();
+        config.show_bazar = form.show_bazar.is_some();
+        config.show_swaps = form.show_swaps.is_some();
+        config.show_wanted = form.show_wanted.is_some();
+        config.show_bumped = form.show_bumped.is_some();
+        config.enrich = form.enrich.is_some();
+    });
+    if updated.is_none() {
+        return not_found(id);
+    }
+
+    Redirect::to(&format!("/dashboard/subscriptions/{id}")).into_response()
+}
+
+fn set_paused(context: AppCtx, id: u64, paused: bool) -> Response {
+    match context.update_subscription(id, |sub| sub.config.paused = paused) {
+        Some(_) => Redirect::to("/dashboard").into_response(),
+        None => not_found(id),
+    }
+}
+
+async fn pause(State(context): State<AppCtx>, Path(id): Path<u64>) -> Response {
+    set_paused(context, id, true)
+}
+
+async fn resume(State(context): State<AppCtx>, Path(id): Path<u64>) -> Response {
+    set_paused(context, id, false)
+}
+
+async fn delete(State(context): State<AppCtx>, Path(id): Path<u64>) -> Response {
+    match context.delete_subscription(id) {
+        true => Redirect::to("/dashboard").into_response(),
+        false => not_found(id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::Listing, web::serve_for_test};
+
+    #[tokio::test]
+    async fn test_dashboard() {
+        let context = AppCtx::default();
+        let id = context.subscription_store.lock().unwrap().add_subscription(
+            "https://hardverapro.hu/aprok/hardver/videokartya/index.html".to_string(),
+            ChannelId::Terminal,
+            OwnerId::Telegram { user_id: None },
+        );
+        {
+            let mut runtime_store = context.runtime_store.lock().unwrap();
+            runtime_store.add(id);
+            runtime_store
+                .record_found(
+                    id,
+                    &[Listing {
+                        img_url: Some("https://example.org/image.jpg".to_string()),
+                        ..Listing::dummy()
+                    }],
+                )
+                .unwrap();
+        }
+        context.status.set_ready();
+        let base = serve_for_test(context, Some("hunter2".to_string())).await;
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+
+        let response = client
+            .get(format!("{base}/dashboard"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::LOCATION.as_str()],
+            "/dashboard/login"
+        );
+
+        let response = client
+            .post(format!("{base}/dashboard/login"))
+            .header(
+                header::CONTENT_TYPE.as_str(),
+                "application/x-www-form-urlencoded",
+            )
+            .body("password=wrong")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = client
+            .post(format!("{base}/dashboard/login"))
+            .header(
+                header::CONTENT_TYPE.as_str(),
+                "application/x-www-form-urlencoded",
+            )
+            .body("password=hunter2")
+            .send()
+            .await
+            .unwrap();
+        let cookie = response.headers()[header::SET_COOKIE.as_str()]
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_string();
+
+        let index = client
+            .get(format!("{base}/dashboard"))
+            .header(header::COOKIE.as_str(), &cookie)
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(index.contains(&format!("/dashboard/subscriptions/{id}")));
+
+        let details = client
+            .get(format!("{base}/dashboard/subscriptions/{id}"))
+            .header(header::COOKIE.as_str(), &cookie)
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(details.contains("https://example.org/image.jpg"));
+        assert!(details.contains("terminal"));
+
+        let response = client
+            .post(format!("{base}/dashboard/subscriptions/{id}/pause"))
+            .header(header::COOKIE.as_str(), &cookie)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    }
+
+    #[test]
+    fn test_sessions() {
+        let session_headers = |cookie: &str| {
+            let mut headers = HeaderMap::new();
+            let token = cookie.split(';').next().unwrap();
+            headers.insert(header::COOKIE, token.parse().unwrap());
+            headers
+        };
+
+        let dashboard = Dashboard::new("hunter2".to_string(), false);
+        let cookie = dashboard.start_session();
+        assert!(cookie.contains(&format!("Max-Age={}", SESSION_LIFETIME.as_secs())));
+        assert!(!cookie.contains("Secure"));
+        assert!(dashboard.session(&session_headers(&cookie)).is_some());
+
+        // Expired sessions are forgotten.
+        for expiry in dashboard.sessions.lock().unwrap().values_mut() {
+            *expiry = Instant::now() - Duration::from_secs(1);
+        }
+        assert!(dashboard.session(&session_headers(&cookie)).is_none());
+        assert!(dashboard.sessions.lock().unwrap().is_empty());
+
+        let secure = Dashboard::new("hunter2".to_string(), true);
+        assert!(secure.start_session().ends_with("; Secure"));
+
+        assert!(secrets_match("hunter2", "hunter2"));
+        assert!(!secrets_match("hunter", "hunter2"));
+    }
+}
@@ -0,0 +1,274 @@
+//! Atom feeds of the listings found by a subscription, or by every subscription of a channel.
+//!
+//! Feeds can't require a login, since feed readers can't do one. Instead their URLs contain a
+//! random token, and unknown tokens look the same as unknown feeds.
+
+use std::{cmp::Reverse, collections::HashSet};
+
+use axum::{
+    Router,
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::{
+    AppCtx,
+    integrations::escape_html,
+    models::{FoundListing, format_timestamp},
+    parsers::seller_slug,
+    storage::RECENT_LISTINGS,
+};
+
+pub fn routes() -> Router<AppCtx> {
+    Router::new()
+        .route("/feeds/subscriptions/{file}", get(subscription_feed))
+        .route("/feeds/channels/{file}", get(channel_feed))
+}
+
+/// The path of the feed of a subscription, relative to the public URL.
+pub fn subscription_feed_path(token: &str) -> String {
+    format!("/feeds/subscriptions/{token}.xml")
+}
+
+/// The path of the feed of a channel, relative to the public URL.
+pub fn channel_feed_path(token: &str) -> String {
+    format!("/feeds/channels/{token}.xml")
+}
+
+/// `/feeds/subscriptions/{token}.xml`
+async fn subscription_feed(State(context): State<AppCtx>, Path(file): Path<String>) -> Response {
+    let Some(token) = file.strip_suffix(".xml") else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some((id, entries)) = ({
+        let runtime_store = context.runtime_store.lock().unwrap();
+        runtime_store
+            .subscription_for_feed(token)
+            .map(|id| (id, runtime_store.recent_listings(id).to_vec()))
+    }) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(subscription) = context
+        .subscription_store
+        .lock()
+        .unwrap()
+        .get_subscription(id)
+        .cloned()
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let title = subscription.name.as_deref().unwrap_or(&subscription.url);
+    atom_response(render_feed(
+        &format!("urn:rozsdhabot:subscription:{id}"),
+        &format!("rozsdhabot: {title}"),
+        Some(&subscription.url),
+        &entries,
+    ))
+}
+
+/// `/feeds/channels/{token}.xml`
+async fn channel_feed(State(context): State<AppCtx>, Path(file): Path<String>) -> Response {
+    let Some(token) = file.strip_suffix(".xml") else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(settings) = ({
+        let channel_store = context.channel_store.lock().unwrap();
+        channel_store
+            .channel_for_feed(token)
+            .and_then(|channel| channel_store.get(channel))
+            .cloned()
+    }) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let ids: Vec<u64> = context
+        .subscription_store
+        .lock()
+        .unwrap()
+        .list_by_channel(&settings.channel)
+        .iter()
+        .map(|s| s.id)
+        .collect();
+
+    let mut entries: Vec<FoundListing> = {
+        let runtime_store = context.runtime_store.lock().unwrap();
+        ids.iter()
+            .flat_map(|id| runtime_store.recent_listings(*id).iter().cloned())
+            .filter(|found| !settings.is_seller_blocked(&found.listing, seller_slug))
+            .collect()
+    };
+    entries.sort_by_key(|found| Reverse(found.found_at));
+    // The same listing can be found by more than one subscription.
+    let mut seen = HashSet::new();
+    entries.retain(|found| seen.insert(found.listing.url.clone()));
+    entries.truncate(RECENT_LISTINGS);
+
+    atom_response(render_feed(
+        &format!("urn:rozsdhabot:channel:{token}"),
+        "rozsdhabot: every subscription of the channel",
+        None,
+        &entries,
+    ))
+}
+
+fn atom_response(body: String) -> Response {
+    (
+        [(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
+
+fn rfc3339(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Renders an Atom feed. `entries` are expected newest first.
+fn render_feed(id: &str, title: &str, link: Option<&str>, entries: &[FoundListing]) -> String {
+    let updated = entries
+        .iter()
+        .map(|f| f.found_at)
+        .max()
+        .unwrap_or_else(Utc::now);
+
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    xml += "\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n";
+    xml += &format!("<id>{}</id>\n", escape_html(id));
+    xml += &format!("<title>{}</title>\n", escape_html(title));
+    xml += &format!("<updated>{}</updated>\n", rfc3339(&updated));
+    xml += "<author><name>rozsdhabot</name></author>\n";
+    if let Some(link) = link {
+        xml += &format!("<link rel=\"alternate\" href=\"{}\"/>\n", escape_html(link));
+    }
+
+    for FoundListing { found_at, listing } in entries {
+        let mut summary = vec![listing.price.to_string(), listing.cities.join(", ")];
+        if !listing.seller_name.is_empty() {
+            summary.push(format!(
+                "{} (+{})",
+                listing.seller_name, listing.seller_ratings
+            ));
+        }
+        if let Some(date) = &listing.date {
+            summary.push(format_timestamp(date));
+        }
+
+        // The content is HTML, escaped once more to fit in the XML.
+        let mut content = String::new();
+        if let Some(image) = &listing.img_url {
+            content += &format!("<p><img src=\"{}\"/></p>", escape_html(image));
+        }
+        content += &format!("<p>{}</p>", escape_html(&summary.join(" | ")));
+        if let Some(details) = &listing.details {
+            content += &format!("<p>{}</p>", escape_html(&details.description));
+        }
+
+        xml += "<entry>\n";
+        xml += &format!("<id>{}</id>\n", escape_html(&listing.url));
+        xml += &format!(
+            "<title>{}</title>\n",
+            escape_html(&format!("{} - {}", listing.title, listing.price))
+        );
+        xml += &format!(
+            "<link rel=\"alternate\" href=\"{}\"/>\n",
+            escape_html(&listing.url)
+        );
+        xml += &format!("<updated>{}</updated>\n", rfc3339(found_at));
+        if let Some(date) = &listing.date {
+            xml += &format!("<published>{}</published>\n", rfc3339(date));
+        }
+        xml += &format!("<summary>{}</summary>\n", escape_html(&summary.join(" | ")));
+        xml += &format!(
+            "<content type=\"html\">{}</content>\n",
+            escape_html(&content)
+        );
+        xml += "</entry>\n";
+    }
+
+    xml += "</feed>\n";
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{ChannelId, Listing, OwnerId},
+        web::serve_for_test,
+    };
+
+    #[test]
+    fn test_render_feed() {
+        let listing = Listing {
+            title: "RTX <3080> & co".to_string(),
+            ..Listing::dummy()
+        };
+        let xml = render_feed(
+            "urn:rozsdhabot:subscription:1",
+            "rozsdhabot: gpu",
+            Some("https://hardverapro.hu/aprok/hardver/videokartya/index.html?a=1&b=2"),
+            &[FoundListing {
+                found_at: Utc::now(),
+                listing: listing.clone(),
+            }],
+        );
+
+        assert!(xml.starts_with("<?xml"));
+        assert_eq!(xml.matches("<entry>").count(), 1);
+        assert!(xml.contains("RTX &lt;3080&gt; &amp; co"));
+        assert!(xml.contains("index.html?a=1&amp;b=2"));
+        assert!(xml.contains(&format!("<id>{}</id>", escape_html(&listing.url))));
+    }
+
+    #[tokio::test]
+    async fn test_feed_tokens() {
+        let context = AppCtx::default();
+        let id = context.subscription_store.lock().unwrap().add_subscription(
+            "https://hardverapro.hu/aprok/hardver/videokartya/index.html".to_string(),
+            ChannelId::Terminal,
+            OwnerId::Telegram { user_id: None },
+        );
+        let (token, channel_token) = {
+            let mut runtime_store = context.runtime_store.lock().unwrap();
+            runtime_store.add(id);
+            runtime_store.record_found(id, &[Listing::dummy()]).unwrap();
+            let token = runtime_store.feed_token(id, false).unwrap();
+            assert_eq!(token, runtime_store.feed_token(id, false).unwrap());
+            let channel_token = context
+                .channel_store
+                .lock()
+                .unwrap()
+                .feed_token(ChannelId::Terminal, false)
+                .unwrap();
+            (token, channel_token)
+        };
+        context.status.set_ready();
+        let base = serve_for_test(context, None).await;
+
+        let get = |path: String| {
+            let url = format!("{base}{path}");
+            async move { reqwest::get(url).await.unwrap() }
+        };
+
+        let response = get(subscription_feed_path(&token)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.text().await.unwrap().contains("<entry>"));
+
+        let response = get(channel_feed_path(&channel_token)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.text().await.unwrap().contains("<entry>"));
+
+        for path in [
+            subscription_feed_path("wrong"),
+            format!("/feeds/subscriptions/{id}.xml"),
+            subscription_feed_path(&channel_token),
+            channel_feed_path(&token),
+        ] {
+            assert_eq!(get(path).await.status(), StatusCode::NOT_FOUND);
+        }
+    }
+}
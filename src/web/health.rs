@@ -0,0 +1,116 @@
+//! Health checks for container orchestration.
+//!
+//! `/healthz` fails if an integration has been disconnected for a long time, restarting the
+//! process is the fix for that. `/readyz` fails until every saved subscription is started and
+//! every integration is connected, and whenever an integration is disconnected.
+
+use std::collections::BTreeMap;
+
+use axum::{
+    Json, Router,
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde::Serialize;
+
+use crate::{AppCtx, status::Connection};
+
+pub fn routes() -> Router<AppCtx> {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+}
+
+#[derive(Serialize)]
+struct Report {
+    /// Whether the check passed.
+    ok: bool,
+    /// Whether every saved subscription is started.
+    started: bool,
+    integrations: BTreeMap<&'static str, Connection>,
+}
+
+fn report(context: &AppCtx, ok: bool) -> Response {
+    let status = match ok {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    let report = Report {
+        ok,
+        started: context.status.is_ready(),
+        integrations: context.status.connections(),
+    };
+    (status, Json(report)).into_response()
+}
+
+async fn healthz(State(context): State<AppCtx>) -> Response {
+    report(&context, context.status.is_healthy())
+}
+
+async fn readyz(State(context): State<AppCtx>) -> Response {
+    report(&context, context.status.is_fully_ready())
+}
+
+/// The subscription store is locked while the saved subscriptions are started, so the routes that
+/// read it answer 503 until then instead of waiting.
+pub async fn require_started(
+    State(context): State<AppCtx>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match context.status.is_ready() {
+        true => next.run(request).await,
+        false => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Starting up, try again later",
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{status::ConnectionState, web::serve_for_test};
+
+    #[tokio::test]
+    async fn test_health_checks() {
+        let context = AppCtx::default();
+        context
+            .status
+            .set_connection("discord", ConnectionState::Connecting, None);
+        let base = serve_for_test(context.clone(), None).await;
+        let status = |path: &'static str| {
+            let url = format!("{base}{path}");
+            async move { reqwest::get(url).await.unwrap().status() }
+        };
+
+        assert_eq!(status("/healthz").await, StatusCode::OK);
+        assert_eq!(status("/readyz").await, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            status("/feeds/subscriptions/1.xml").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        context.status.set_ready();
+        context
+            .status
+            .set_connection("discord", ConnectionState::Connected, None);
+        assert_eq!(status("/readyz").await, StatusCode::OK);
+        assert_eq!(
+            status("/feeds/subscriptions/1.xml").await,
+            StatusCode::NOT_FOUND
+        );
+
+        let report: serde_json::Value = reqwest::get(format!("{base}/readyz"))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(report["integrations"]["discord"]["state"], "connected");
+    }
+}
@@ -0,0 +1,96 @@
+//! The embedded HTTP server for the feeds, the dashboard, the JSON API, the metrics and the health
+//! checks. Only started if an address is configured.
+
+use std::net::SocketAddr;
+
+use axum::{
+    Router, extract::State, http::header, middleware, response::IntoResponse, routing::get,
+};
+
+use crate::{AppCtx, metrics::METRICS};
+
+mod api;
+mod dashboard;
+mod feeds;
+mod health;
+
+pub use feeds::{channel_feed_path, subscription_feed_path};
+
+#[derive(Debug, Clone)]
+pub struct HttpSettings {
+    pub address: SocketAddr,
+    /// The URL the server is reachable at from the outside, used in the links given to users.
+    /// No trailing slash.
+    pub public_url: String,
+    /// The dashboard is only served if a password is set.
+    pub dashboard_password: Option<String>,
+    /// The JSON API is only served if the api integration is enabled.
+    pub api_tokens: Option<Vec<String>>,
+}
+
+fn router(
+    context: AppCtx,
+    dashboard_password: Option<String>,
+    api_tokens: Option<Vec<String>>,
+) -> Router {
+    let mut started = feeds::routes();
+    if let Some(tokens) = api_tokens {
+        started = started.merge(api::routes(tokens));
+    }
+    if let Some(password) = dashboard_password {
+        // Browsers only send secure cookies over HTTPS, so it depends on how users reach us.
+        let secure = context
+            .public_url
+            .as_deref()
+            .is_some_and(|url| url.starts_with("https://"));
+        started = started.merge(dashboard::routes(password, secure));
+    }
+    let started = started.route_layer(middleware::from_fn_with_state(
+        context.clone(),
+        health::require_started,
+    ));
+
+    Router::new()
+        .route("/metrics", get(metrics))
+        .merge(health::routes())
+        .merge(started)
+        .with_state(context)
+}
+
+/// `/metrics`, for Prometheus.
+async fn metrics(State(context): State<AppCtx>) -> impl IntoResponse {
+    let active_monitors = context.monitor_manager.lock().unwrap().running_count();
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        METRICS.lock().unwrap().render(active_monitors),
+    )
+}
+
+/// Serves until the application exits. Failing to bind is logged, the rest of the bot keeps
+/// running.
+pub async fn serve(settings: HttpSettings, context: AppCtx) {
+    let address = settings.address;
+    let listener = match tokio::net::TcpListener::bind(address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!("Failed to start the HTTP server on {address}: {e}");
+            return;
+        }
+    };
+    tracing::info!("HTTP server listening on {address}");
+
+    let router = router(context, settings.dashboard_password, settings.api_tokens);
+    if let Err(e) = axum::serve(listener, router).await {
+        tracing::error!("HTTP server stopped: {e}");
+    }
+}
+
+#[cfg(test)]
+/// Starts the router on a random local port, returning its base URL.
+async fn serve_for_test(context: AppCtx, dashboard_password: Option<String>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let router = router(context, dashboard_password, None);
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    url
+}